@@ -2,13 +2,54 @@ use std::fmt::Write;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
-use std::write;
 
 fn main() {
     let input = load_input("input");
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        Some("report") => {
+            for (i, report) in strategy_table(&input).iter().enumerate() {
+                println!("Race {}: {}", i + 1, report);
+            }
+            match connect_numbers(&input) {
+                Ok((time, distance)) => println!("Combined: {}", RaceReport::new(time, distance)),
+                Err(e) => println!("Can't combine the races: {}", e),
+            }
+            return;
+        }
+        Some("curve") => {
+            let race = args
+                .next()
+                .and_then(|n| n.parse::<usize>().ok())
+                .filter(|race| (1..=input.len()).contains(race));
+            match race {
+                Some(race) => {
+                    let (time, distance) = input[race - 1];
+                    print!("{}", RaceReport::new(time, distance).curve_csv());
+                }
+                None => println!("Usage: curve <race number from 1 to {}>", input.len()),
+            }
+            return;
+        }
+        Some("merge") => {
+            match parse_merge_options(args)
+                .and_then(|options| merge_races(&input, &options).map_err(|e| e.to_string()))
+            {
+                Ok((time, distance)) => println!("Merged: {}", RaceReport::new(time, distance)),
+                Err(e) => println!("Can't merge races: {}", e),
+            }
+            return;
+        }
+        _ => (),
+    }
     println!("Solution for part 1: {}", part_1(&input));
-    println!("Solution for part 2: {}", part_2(&input));
-    println!("Solution for part 2 using math: {}", part_2_math(&input));
+    match (part_2(&input), part_2_math(&input)) {
+        (Ok(ways), Ok(ways_math)) => {
+            println!("Solution for part 2: {}", ways);
+            println!("Solution for part 2 using math: {}", ways_math);
+        }
+        (Err(e), _) | (_, Err(e)) => println!("Can't combine the races for part 2: {}", e),
+    }
 }
 
 fn part_1(pairs: &[(u64, u64)]) -> usize {
@@ -27,8 +68,8 @@ fn part_1(pairs: &[(u64, u64)]) -> usize {
     result
 }
 
-fn part_2(pairs: &[(u64, u64)]) -> u64 {
-    let (time, distance) = connect_numbers(pairs);
+fn part_2(pairs: &[(u64, u64)]) -> Result<u64, MergeError> {
+    let (time, distance) = connect_numbers(pairs)?;
 
     let mut result = 0;
     for t in 1..time {
@@ -36,11 +77,27 @@ fn part_2(pairs: &[(u64, u64)]) -> u64 {
             result += 1;
         }
     }
-    result
+    Ok(result)
 }
 
-#[allow(clippy::neg_multiply)]
-fn part_2_math(pairs: &[(u64, u64)]) -> u64 {
+fn part_2_math(pairs: &[(u64, u64)]) -> Result<u64, MergeError> {
+    let (time, distance) = connect_numbers(pairs)?;
+    Ok(match winning_range(time, distance) {
+        Some((min_hold, max_hold)) => max_hold - min_hold + 1,
+        None => 0,
+    })
+}
+
+fn beats_record(time: u64, hold_time: u64, distance: u64) -> bool {
+    travelled(time, hold_time) > distance as u128
+}
+
+fn travelled(time: u64, hold_time: u64) -> u128 {
+    (time - hold_time) as u128 * hold_time as u128
+}
+
+/// Returns the smallest and biggest hold time that beat the record, `None` if nothing does
+fn winning_range(time: u64, distance: u64) -> Option<(u64, u64)> {
     // To check if a hold_time will beat the distance with a given time you can use this:
     // (time - hold_time) * hold_time > distance
     // time = total time
@@ -61,39 +118,247 @@ fn part_2_math(pairs: &[(u64, u64)]) -> u64 {
     // The time and distance are known meaning you can calculate the hold_time by finding the root of quadratic equation
     // This will give you the smallest and biggest hold_time that beats the record
     // x = (-b +/- √(b^2 - 4ac)) / 2a
-    let (time, distance) = connect_numbers(pairs);
-    let time = time as i64;
-    let distance = distance as i64;
+    //
     // Since c (distance) and a (-1) are negative you can just skip the negatives
-    let delta = time.pow(2) - (4 * distance);
-    let square_root = (delta as f64).sqrt();
+    let delta = (time as f64).powi(2) - (4.0 * distance as f64);
+    if delta < 0.0 {
+        return None;
+    }
+    let square_root = delta.sqrt();
 
-    let x_1 = (-time as f64 + square_root) / -2.0;
-    let x_2 = (-time as f64 - square_root) / -2.0;
     // x_1 get ceil because we need the next full number that beats the record
     // x_2 gets floor because we need the last full number that beats the record
-    let diff = x_2.floor() - x_1.ceil();
-    diff as u64 + 1
+    let mut x_1 = ((time as f64 - square_root) / 2.0).ceil() as u64;
+    let mut x_2 = (((time as f64 + square_root) / 2.0).floor() as u64).min(time);
+
+    // Floats aren't exact for big numbers and a root that only ties the record doesn't count
+    // so the edges need to be nudged into place
+    while x_1 > 0 && beats_record(time, x_1 - 1, distance) {
+        x_1 -= 1;
+    }
+    while x_1 <= x_2 && !beats_record(time, x_1, distance) {
+        x_1 += 1;
+    }
+    while x_2 < time && beats_record(time, x_2 + 1, distance) {
+        x_2 += 1;
+    }
+    while x_2 >= x_1 && !beats_record(time, x_2, distance) {
+        if x_2 == 0 {
+            break;
+        }
+        x_2 -= 1;
+    }
+    if x_1 <= x_2 && beats_record(time, x_1, distance) {
+        Some((x_1, x_2))
+    } else {
+        None
+    }
 }
 
-fn connect_numbers(pairs: &[(u64, u64)]) -> (u64, u64) {
-    let time = pairs
-        .iter()
-        .fold(String::new(), |mut acc, p| {
-            let _ = write!(acc, "{}", p.0);
-            acc
-        })
-        .parse::<u64>()
-        .unwrap();
-    let distance = pairs
+#[derive(Debug, PartialEq, Eq)]
+struct RaceReport {
+    time: u64,
+    distance: u64,
+    winning_range: Option<(u64, u64)>,
+    best_hold: u64,
+    best_distance: u128,
+    margin: i128,
+}
+
+impl RaceReport {
+    fn new(time: u64, distance: u64) -> Self {
+        // The parabola peaks at time / 2, for odd times both neighbours give the same distance
+        let best_hold = time / 2;
+        let best_distance = travelled(time, best_hold);
+        Self {
+            time,
+            distance,
+            winning_range: winning_range(time, distance),
+            best_hold,
+            best_distance,
+            margin: best_distance as i128 - distance as i128,
+        }
+    }
+
+    fn winning_count(&self) -> u64 {
+        self.winning_range.map_or(0, |(min, max)| max - min + 1)
+    }
+
+    /// Every hold time with the distance it reaches
+    fn curve_csv(&self) -> String {
+        let mut csv = String::from("hold_time,distance,beats_record\n");
+        for hold_time in 0..=self.time {
+            let _ = writeln!(
+                csv,
+                "{},{},{}",
+                hold_time,
+                travelled(self.time, hold_time),
+                beats_record(self.time, hold_time, self.distance)
+            );
+        }
+        csv
+    }
+}
+
+impl std::fmt::Display for RaceReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let range = match self.winning_range {
+            Some((min, max)) => format!("[{}, {}]", min, max),
+            None => "none".to_string(),
+        };
+        write!(
+            f,
+            "time {:>10} record {:>16} wins {:>24} ({} ways) best hold {:>10} reaches {:>16} margin {}",
+            self.time,
+            self.distance,
+            range,
+            self.winning_count(),
+            self.best_hold,
+            self.best_distance,
+            self.margin
+        )
+    }
+}
+
+fn strategy_table(pairs: &[(u64, u64)]) -> Vec<RaceReport> {
+    pairs
         .iter()
-        .fold(String::new(), |mut acc, p| {
-            let _ = write!(acc, "{}", p.1);
-            acc
-        })
-        .parse::<u64>()
-        .unwrap();
-    (time, distance)
+        .map(|(time, distance)| RaceReport::new(*time, *distance))
+        .collect()
+}
+
+fn connect_numbers(pairs: &[(u64, u64)]) -> Result<(u64, u64), MergeError> {
+    merge_races(pairs, &MergeOptions::default())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct MergeOptions {
+    /// 0 based indexes of the races to merge, all of them when `None`
+    races: Option<Vec<usize>>,
+    radix: u32,
+    /// Width in bits the merged numbers have to fit in
+    bits: u32,
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        Self {
+            races: None,
+            radix: 10,
+            bits: 64,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum MergeError {
+    InvalidRadix(u32),
+    InvalidWidth(u32),
+    UnknownRace(usize),
+    NoRaces,
+    Overflow { field: &'static str, bits: u32 },
+}
+
+impl std::fmt::Display for MergeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MergeError::InvalidRadix(radix) => write!(f, "radix {} is not in 2..=36", radix),
+            MergeError::InvalidWidth(bits) => write!(f, "width {} is not in 1..=64", bits),
+            MergeError::UnknownRace(race) => write!(f, "there is no race {}", race + 1),
+            MergeError::NoRaces => write!(f, "no races to merge"),
+            MergeError::Overflow { field, bits } => {
+                write!(f, "merged {} does not fit in {} bits", field, bits)
+            }
+        }
+    }
+}
+
+/// Concatenates the digits of the chosen races in the given radix
+fn merge_races(pairs: &[(u64, u64)], options: &MergeOptions) -> Result<(u64, u64), MergeError> {
+    if !(2..=36).contains(&options.radix) {
+        return Err(MergeError::InvalidRadix(options.radix));
+    }
+    if !(1..=64).contains(&options.bits) {
+        return Err(MergeError::InvalidWidth(options.bits));
+    }
+    let races: Vec<(u64, u64)> = match &options.races {
+        Some(indexes) => indexes
+            .iter()
+            .map(|i| pairs.get(*i).copied().ok_or(MergeError::UnknownRace(*i)))
+            .collect::<Result<_, _>>()?,
+        None => pairs.to_vec(),
+    };
+    if races.is_empty() {
+        return Err(MergeError::NoRaces);
+    }
+
+    let time = concat_digits(races.iter().map(|r| r.0), options.radix, options.bits).ok_or(
+        MergeError::Overflow {
+            field: "time",
+            bits: options.bits,
+        },
+    )?;
+    let distance = concat_digits(races.iter().map(|r| r.1), options.radix, options.bits).ok_or(
+        MergeError::Overflow {
+            field: "distance",
+            bits: options.bits,
+        },
+    )?;
+    Ok((time, distance))
+}
+
+/// `None` if the result doesn't fit in `bits`
+fn concat_digits(numbers: impl Iterator<Item = u64>, radix: u32, bits: u32) -> Option<u64> {
+    let radix = radix as u64;
+    let max = u64::MAX >> (64 - bits);
+    let mut acc: u64 = 0;
+    for n in numbers {
+        if acc == 0 {
+            // Nothing to shift yet, and shifting past n could overflow even though n fits
+            acc = n;
+            continue;
+        }
+        let digits = n.checked_ilog(radix).map_or(1, |log| log + 1);
+        acc = acc
+            .checked_mul(radix.checked_pow(digits)?)?
+            .checked_add(n)?;
+    }
+    (acc <= max).then_some(acc)
+}
+
+fn parse_merge_options(args: impl Iterator<Item = String>) -> Result<MergeOptions, String> {
+    let mut options = MergeOptions::default();
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("missing value for {}", arg))?;
+        match arg.as_str() {
+            "--races" => {
+                options.races = Some(
+                    value
+                        .split(',')
+                        .map(|n| match n.parse::<usize>() {
+                            Ok(race) if race > 0 => Ok(race - 1),
+                            _ => Err(format!("invalid race number {}, races start at 1", n)),
+                        })
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            "--radix" => {
+                options.radix = value
+                    .parse()
+                    .map_err(|_| format!("invalid radix {}", value))?
+            }
+            "--bits" => {
+                options.bits = value
+                    .parse()
+                    .map_err(|_| format!("invalid width {}", value))?
+            }
+            _ => return Err(format!("unknown option {}", arg)),
+        }
+    }
+    Ok(options)
 }
 
 fn load_input(name: &str) -> Vec<(u64, u64)> {
@@ -120,10 +385,110 @@ fn load_input(name: &str) -> Vec<(u64, u64)> {
     times.into_iter().zip(distances).collect()
 }
 
+#[test]
+fn strategy() {
+    let reports = strategy_table(&[(7, 9), (15, 40), (30, 200)]);
+    let ranges: Vec<_> = reports.iter().map(|r| r.winning_range).collect();
+    assert_eq!(ranges, vec![Some((2, 5)), Some((4, 11)), Some((11, 19))]);
+    let best: Vec<_> = reports
+        .iter()
+        .map(|r| (r.best_hold, r.best_distance, r.margin))
+        .collect();
+    assert_eq!(best, vec![(3, 12, 3), (7, 56, 16), (15, 225, 25)]);
+    assert_eq!(winning_range(4, 4), None);
+    assert!(reports[0]
+        .curve_csv()
+        .starts_with("hold_time,distance,beats_record\n0,0,false\n1,6,false\n2,10,true\n"));
+}
+
+#[test]
+fn merging() {
+    let pairs = [(7, 9), (15, 40), (30, 200)];
+    assert_eq!(connect_numbers(&pairs), Ok((71530, 940200)));
+    let options = MergeOptions {
+        races: Some(vec![0, 2]),
+        ..Default::default()
+    };
+    assert_eq!(merge_races(&pairs, &options), Ok((730, 9200)));
+    let options = MergeOptions {
+        radix: 2,
+        ..Default::default()
+    };
+    // 111 1111 11110 and 1001 101000 11001000
+    assert_eq!(
+        merge_races(&pairs, &options),
+        Ok((0b111111111110, 0b100110100011001000))
+    );
+    let options = MergeOptions {
+        bits: 16,
+        ..Default::default()
+    };
+    assert_eq!(
+        merge_races(&pairs, &options),
+        Err(MergeError::Overflow {
+            field: "time",
+            bits: 16
+        })
+    );
+    let long = [(99999, 1); 5];
+    let overflow = Err(MergeError::Overflow {
+        field: "time",
+        bits: 64,
+    });
+    assert_eq!(part_2(&long), overflow);
+    assert_eq!(part_2_math(&long), overflow);
+    assert_eq!(
+        merge_races(&long, &MergeOptions::default()),
+        Err(MergeError::Overflow {
+            field: "time",
+            bits: 64
+        })
+    );
+    // The first number is taken as is, even if the next power of the radix doesn't fit
+    assert_eq!(
+        merge_races(&[(10_000_000_000_000_000_000, 5)], &MergeOptions::default()),
+        Ok((10_000_000_000_000_000_000, 5))
+    );
+    let options = MergeOptions {
+        radix: 2,
+        ..Default::default()
+    };
+    assert_eq!(merge_races(&[(1 << 63, 1)], &options), Ok((1 << 63, 1)));
+    assert_eq!(
+        merge_races(&[(1 << 62, 1), (1, 1)], &options),
+        Ok((1 << 63 | 1, 0b11))
+    );
+    assert_eq!(
+        merge_races(&[(1 << 63, 1), (0, 1)], &options),
+        Err(MergeError::Overflow {
+            field: "time",
+            bits: 64
+        })
+    );
+    let args = |list: &[&str]| list.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+    assert_eq!(
+        parse_merge_options(args(&["--races", "1,3"]).into_iter()),
+        Ok(MergeOptions {
+            races: Some(vec![0, 2]),
+            ..Default::default()
+        })
+    );
+    assert!(parse_merge_options(args(&["--races", "0"]).into_iter()).is_err());
+    assert!(parse_merge_options(args(&["--radix"]).into_iter()).is_err());
+    let options = MergeOptions {
+        races: Some(vec![3]),
+        ..Default::default()
+    };
+    assert_eq!(
+        merge_races(&pairs, &options),
+        Err(MergeError::UnknownRace(3))
+    );
+}
+
 #[test]
 fn example() {
     let input = load_input("example");
     assert_eq!(part_1(&input), 288);
-    assert_eq!(part_2(&input), 71503);
-    assert_eq!(part_2_math(&input), 71503);
+    assert_eq!(part_2(&input), Ok(71503));
+    assert_eq!(part_2_math(&input), Ok(71503));
 }
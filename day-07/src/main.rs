@@ -1,23 +1,109 @@
-use std::cmp::Ordering;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
-use std::vec;
 
 fn main() {
     let input = load_input("input");
+    if let Some("explain") = std::env::args().nth(1).as_deref() {
+        let joker = std::env::args().nth(2).as_deref() == Some("joker");
+        for ranked in explain(&input, joker) {
+            println!("{}", ranked);
+        }
+        return;
+    }
     println!("Solution for part 1: {:?}", solve(&input, false));
     println!("Solution for part 1: {:?}", solve(&input, true));
 }
 
 fn solve(input: &[(String, u64)], part_2: bool) -> u64 {
-    let mut hands = turn_into_hands(input, part_2);
+    let game = if part_2 {
+        CardGame::jokers()
+    } else {
+        CardGame::standard()
+    };
+    solve_with(input, &game).unwrap()
+}
+
+fn solve_with(input: &[(String, u64)], game: &CardGame) -> Result<u64, RankError> {
+    let mut hands = turn_into_hands(input, game)?;
     hands.sort_unstable();
     let mut sum = 0;
     for (i, hand) in hands.iter().enumerate() {
         sum += hand.bid * (i as u64 + 1);
     }
-    sum
+    Ok(sum)
+}
+
+fn explain(input: &[(String, u64)], part_2: bool) -> Vec<RankedHand> {
+    let game = if part_2 {
+        CardGame::jokers()
+    } else {
+        CardGame::standard()
+    };
+    explain_with(input, &game).unwrap()
+}
+
+/// Same ordering as `solve_with` but keeps everything that went into it
+fn explain_with(input: &[(String, u64)], game: &CardGame) -> Result<Vec<RankedHand>, RankError> {
+    let hands = turn_into_hands(input, game)?;
+    let mut order: Vec<usize> = (0..hands.len()).collect();
+    order.sort_unstable_by_key(|i| hands[*i]);
+
+    let mut counter = vec![0; game.order.len()];
+    let mut ranked = Vec::with_capacity(order.len());
+    for (i, &index) in order.iter().enumerate() {
+        let (cards, bid) = &input[index];
+        let (hand_type, _) = game.count(cards, &mut counter)?;
+        let rank = i as u64 + 1;
+        let key = hands[index].key;
+        let tie = (i > 0 && hands[order[i - 1]].key == key)
+            || order.get(i + 1).is_some_and(|n| hands[*n].key == key);
+        ranked.push(RankedHand {
+            cards: cards.clone(),
+            bid: *bid,
+            hand_type,
+            substitution: game.substitution(cards, &counter),
+            rank,
+            winnings: bid * rank,
+            tie,
+        });
+    }
+    Ok(ranked)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct RankedHand {
+    cards: String,
+    bid: u64,
+    hand_type: HandType,
+    /// The card the wildcards pretend to be
+    substitution: Option<char>,
+    rank: u64,
+    winnings: u64,
+    /// Another hand has the same type and card values
+    tie: bool,
+}
+
+impl std::fmt::Display for RankedHand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:>6} {} {:<9} {:>5} * {:>4} = {:>8}",
+            self.rank,
+            self.cards,
+            format!("{:?}", self.hand_type),
+            self.bid,
+            self.rank,
+            self.winnings
+        )?;
+        if let Some(c) = self.substitution {
+            write!(f, " wildcards as {}", c)?;
+        }
+        if self.tie {
+            write!(f, " TIE")?;
+        }
+        Ok(())
+    }
 }
 
 fn load_input(name: &str) -> Vec<(String, u64)> {
@@ -34,100 +120,166 @@ fn load_input(name: &str) -> Vec<(String, u64)> {
     data
 }
 
-fn turn_into_hands(input: &[(String, u64)], joker: bool) -> Vec<Hand> {
-    let mut hands = Vec::new();
-    for (s, bid) in input {
-        let cards: Vec<char> = s.chars().collect();
-        let card_values: Vec<u64> = cards.iter().map(|n| get_card_value(n, joker)).collect();
-        let hand_type = HandType::new_from_cards(&cards, joker);
+fn turn_into_hands(input: &[(String, u64)], game: &CardGame) -> Result<Vec<Hand>, RankError> {
+    game.check_key_width()?;
+    let mut counter = vec![0; game.order.len()];
+    let mut hands = Vec::with_capacity(input.len());
+    for (cards, bid) in input {
         hands.push(Hand {
-            cards,
-            card_values,
+            key: game.encode(cards, &mut counter)?,
             bid: *bid,
-            hand_type,
         });
     }
 
-    hands
-}
-
-fn get_card_value(card: &char, joker: bool) -> u64 {
-    if joker {
-        match card {
-            '2' => 1,
-            '3' => 2,
-            '4' => 3,
-            '5' => 4,
-            '6' => 5,
-            '7' => 6,
-            '8' => 7,
-            '9' => 8,
-            'T' => 9,
-            'J' => 0,
-            'Q' => 10,
-            'K' => 11,
-            'A' => 12,
-            _ => unreachable!(),
-        }
-    } else {
-        match card {
-            '2' => 0,
-            '3' => 1,
-            '4' => 2,
-            '5' => 3,
-            '6' => 4,
-            '7' => 5,
-            '8' => 6,
-            '9' => 7,
-            'T' => 8,
-            'J' => 9,
-            'Q' => 10,
-            'K' => 11,
-            'A' => 12,
-            _ => unreachable!(),
-        }
-    }
+    Ok(hands)
 }
 
-#[derive(Debug, Eq, Clone)]
-struct Hand {
-    cards: Vec<char>,
-    card_values: Vec<u64>,
-    bid: u64,
-    hand_type: HandType,
+/// The rules of a game: card strength, which cards are wild and how many cards make a hand
+#[derive(Debug, Clone)]
+struct CardGame {
+    /// Weakest card first
+    order: Vec<char>,
+    wildcards: Vec<char>,
+    hand_size: usize,
 }
 
-impl PartialEq for Hand {
-    fn eq(&self, other: &Self) -> bool {
-        self.hand_type == other.hand_type && self.cards == other.cards
-    }
+#[derive(Debug, PartialEq, Eq)]
+enum RankError {
+    UnknownCard(char),
+    WrongSize { expected: usize, found: usize },
+    NoCategory(String),
+    KeyTooWide { bits: u32 },
 }
 
-impl Ord for Hand {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        if self.hand_type == other.hand_type {
-            for (x, y) in self.card_values.iter().zip(other.card_values.iter()) {
-                match x.cmp(y) {
-                    Ordering::Less => {
-                        return Ordering::Less;
-                    }
-                    Ordering::Equal => (),
-                    Ordering::Greater => {
-                        return Ordering::Greater;
-                    }
-                }
+impl std::fmt::Display for RankError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RankError::UnknownCard(c) => write!(f, "card {:?} is not in the deck", c),
+            RankError::WrongSize { expected, found } => {
+                write!(f, "hand has {} cards instead of {}", found, expected)
+            }
+            RankError::NoCategory(hand) => write!(f, "hand {} doesn't fit any hand type", hand),
+            RankError::KeyTooWide { bits } => {
+                write!(f, "hands need {} bits which doesn't fit in a u64 key", bits)
             }
-            Ordering::Equal
-        } else {
-            self.hand_type.cmp(&other.hand_type)
         }
     }
 }
 
-impl PartialOrd for Hand {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
+impl CardGame {
+    fn new(order: &str, wildcards: &str, hand_size: usize) -> Self {
+        Self {
+            order: order.chars().collect(),
+            wildcards: wildcards.chars().collect(),
+            hand_size,
+        }
+    }
+
+    fn standard() -> Self {
+        Self::new("23456789TJQKA", "", 5)
+    }
+
+    fn jokers() -> Self {
+        Self::new("J23456789TQKA", "J", 5)
+    }
+
+    fn card_value(&self, card: char) -> Option<u64> {
+        self.order.iter().position(|c| *c == card).map(|v| v as u64)
+    }
+
+    fn is_wild(&self, card: char) -> bool {
+        self.wildcards.contains(&card)
+    }
+
+    /// Bits needed to store the value of a single card
+    fn card_bits(&self) -> u32 {
+        (usize::BITS - self.order.len().saturating_sub(1).leading_zeros()).max(1)
+    }
+
+    fn check_key_width(&self) -> Result<(), RankError> {
+        // 3 bits for the hand type
+        let bits = 3 + self.card_bits() * self.hand_size as u32;
+        if bits > u64::BITS {
+            Err(RankError::KeyTooWide { bits })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Packs the hand into a single number that sorts the same way as the hands do.
+    /// The hand type takes the top bits followed by each card value in order.
+    /// `counter` is scratch space with one slot per card in the deck so no allocations are needed.
+    fn encode(&self, cards: &str, counter: &mut [u64]) -> Result<u64, RankError> {
+        let (hand_type, ranks) = self.count(cards, counter)?;
+        Ok(((hand_type as u64) << (self.card_bits() * self.hand_size as u32)) | ranks)
     }
+
+    /// Which card the wildcards should become, `counter` has to be filled by `count` for the same hand
+    fn substitution(&self, cards: &str, counter: &[u64]) -> Option<char> {
+        if !cards.chars().any(|c| self.is_wild(c)) {
+            return None;
+        }
+        let best = counter
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| **n > 0)
+            .max_by_key(|(i, n)| (**n, *i))
+            .map(|(i, _)| self.order[i]);
+        // A hand of only wildcards becomes the strongest card
+        best.or_else(|| {
+            self.order
+                .iter()
+                .rev()
+                .find(|c| !self.is_wild(**c))
+                .copied()
+        })
+    }
+
+    fn count(&self, cards: &str, counter: &mut [u64]) -> Result<(HandType, u64), RankError> {
+        counter.fill(0);
+        let card_bits = self.card_bits();
+        let mut ranks = 0;
+        let mut size = 0;
+        let mut wild_count = 0;
+        for c in cards.chars() {
+            let value = self.card_value(c).ok_or(RankError::UnknownCard(c))?;
+            ranks = (ranks << card_bits) | value;
+            size += 1;
+            if self.is_wild(c) {
+                wild_count += 1;
+            } else {
+                counter[value as usize] += 1;
+            }
+        }
+        if size != self.hand_size {
+            return Err(RankError::WrongSize {
+                expected: self.hand_size,
+                found: size,
+            });
+        }
+
+        let mut biggest = 0;
+        let mut second = 0;
+        for &n in counter.iter() {
+            if n > biggest {
+                second = biggest;
+                biggest = n;
+            } else if n > second {
+                second = n;
+            }
+        }
+        // Wildcards always do best by joining the biggest group
+        let hand_type = HandType::new_from_counts(biggest + wild_count, second)
+            .ok_or_else(|| RankError::NoCategory(cards.to_string()))?;
+        Ok((hand_type, ranks))
+    }
+}
+
+/// `key` holds the hand type and card values, see `CardGame::encode`
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+struct Hand {
+    key: u64,
+    bid: u64,
 }
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
@@ -142,44 +294,27 @@ enum HandType {
 }
 
 impl HandType {
-    fn new_from_cards(cards: &[char], joker: bool) -> Self {
-        let mut counter: Vec<u64> = vec![0; 13];
-        let mut joker_count = 0;
-        if joker {
-            for c in cards {
-                if *c == 'J' {
-                    joker_count += 1;
-                } else {
-                    counter[get_card_value(c, false) as usize] += 1;
-                }
-            }
-        } else {
-            for c in cards {
-                counter[get_card_value(c, false) as usize] += 1;
-            }
-        }
-        counter.sort_unstable();
-        counter.reverse();
-        counter[0] += joker_count;
-        match counter[0] {
-            5 => Self::Five,
-            4 => Self::Four,
+    /// Uses the two biggest groups of the same card
+    fn new_from_counts(biggest: u64, second: u64) -> Option<Self> {
+        match biggest {
+            5 => Some(Self::Five),
+            4 => Some(Self::Four),
             3 => {
-                if counter[1] == 2 {
-                    Self::FullHouse
+                if second >= 2 {
+                    Some(Self::FullHouse)
                 } else {
-                    Self::Three
+                    Some(Self::Three)
                 }
             }
             2 => {
-                if counter[1] == 2 {
-                    Self::TwoPair
+                if second == 2 {
+                    Some(Self::TwoPair)
                 } else {
-                    Self::Pair
+                    Some(Self::Pair)
                 }
             }
-            1 => Self::Single,
-            _ => unreachable!(),
+            1 => Some(Self::Single),
+            _ => None,
         }
     }
 }
@@ -188,6 +323,154 @@ impl HandType {
 mod tests {
     use super::*;
 
+    fn classify(game: &CardGame, cards: &str) -> Result<HandType, RankError> {
+        let mut counter = vec![0; game.order.len()];
+        game.count(cards, &mut counter)
+            .map(|(hand_type, _)| hand_type)
+    }
+
+    #[test]
+    fn card_game() {
+        let game = CardGame::jokers();
+        assert_eq!(classify(&game, "KTJJT"), Ok(HandType::Four));
+        assert_eq!(
+            classify(&CardGame::standard(), "KTJJT"),
+            Ok(HandType::TwoPair)
+        );
+
+        let small = CardGame::new("123", "3", 3);
+        assert_eq!(classify(&small, "113"), Ok(HandType::Three));
+        assert_eq!(
+            classify(&small, "1233"),
+            Err(RankError::WrongSize {
+                expected: 3,
+                found: 4
+            })
+        );
+        assert_eq!(classify(&small, "124"), Err(RankError::UnknownCard('4')));
+
+        let big = CardGame::new("AB", "", 6);
+        assert_eq!(
+            classify(&big, "AAAAAA"),
+            Err(RankError::NoCategory("AAAAAA".to_string()))
+        );
+
+        let input = vec![("32T3K".to_string(), 765), ("T55J5".to_string(), 684)];
+        assert_eq!(solve_with(&input, &CardGame::standard()), Ok(765 + 684 * 2));
+    }
+
+    #[test]
+    fn packed_keys() {
+        let game = CardGame::jokers();
+        let mut counter = vec![0; game.order.len()];
+        let mut encode = |hands: &[&str]| -> Vec<u64> {
+            hands
+                .iter()
+                .map(|h| game.encode(h, &mut counter).unwrap())
+                .collect()
+        };
+        let sorted = encode(&["32T3K", "JKKK2", "T55J5", "QQQJA", "QQQQ2", "KTJJT"]);
+        let mut keys = encode(&["QQQQ2", "KTJJT", "32T3K", "T55J5", "JKKK2", "QQQJA"]);
+        keys.sort_unstable();
+        assert_eq!(keys, sorted);
+        assert_eq!(keys[0] >> 20, HandType::Pair as u64);
+        assert_eq!(keys[1] >> 20, HandType::Four as u64);
+
+        let wide = CardGame::new("23456789TJQKA", "", 20);
+        assert_eq!(
+            wide.check_key_width(),
+            Err(RankError::KeyTooWide { bits: 83 })
+        );
+    }
+
+    #[test]
+    fn explain_ranking() {
+        let input: Vec<(String, u64)> =
+            [("32T3K", 765), ("KTJJT", 220), ("JJJJJ", 10), ("32T3K", 5)]
+                .iter()
+                .map(|(c, b)| (c.to_string(), *b))
+                .collect();
+        let ranked = explain(&input, true);
+        let summary: Vec<_> = ranked
+            .iter()
+            .map(|r| {
+                (
+                    r.cards.as_str(),
+                    r.hand_type,
+                    r.substitution,
+                    r.winnings,
+                    r.tie,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("32T3K", HandType::Pair, None, 5, true),
+                ("32T3K", HandType::Pair, None, 765 * 2, true),
+                ("KTJJT", HandType::Four, Some('T'), 220 * 3, false),
+                ("JJJJJ", HandType::Five, Some('A'), 10 * 4, false),
+            ]
+        );
+        let total: u64 = ranked.iter().map(|r| r.winnings).sum();
+        assert_eq!(total, solve(&input, true));
+        assert_eq!(explain(&input, false)[3].hand_type, HandType::Five);
+    }
+
+    /// Compares against hands stored the old way with a `Vec` of cards and values.
+    /// Run with `cargo test --release -- --ignored --nocapture bench_sort`
+    #[test]
+    #[ignore]
+    fn bench_sort() {
+        #[derive(PartialEq, Eq, PartialOrd, Ord)]
+        struct VecHand {
+            hand_type: HandType,
+            card_values: Vec<u64>,
+            cards: Vec<char>,
+            bid: u64,
+        }
+
+        let game = CardGame::jokers();
+        let mut seed: u64 = 0x2023_0007;
+        let mut input = Vec::new();
+        for _ in 0..2_000_000 {
+            let mut cards = String::new();
+            for _ in 0..5 {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                cards.push(game.order[(seed % 13) as usize]);
+            }
+            input.push((cards, seed % 1000));
+        }
+
+        let start = std::time::Instant::now();
+        let mut vec_hands: Vec<VecHand> = input
+            .iter()
+            .map(|(s, bid)| {
+                let cards: Vec<char> = s.chars().collect();
+                VecHand {
+                    hand_type: classify(&game, s).unwrap(),
+                    card_values: cards.iter().map(|c| game.card_value(*c).unwrap()).collect(),
+                    cards,
+                    bid: *bid,
+                }
+            })
+            .collect();
+        vec_hands.sort_unstable();
+        let vec_time = start.elapsed();
+
+        let start = std::time::Instant::now();
+        let mut hands = turn_into_hands(&input, &game).unwrap();
+        hands.sort_unstable();
+        let packed_time = start.elapsed();
+
+        let vec_bids: Vec<u64> = vec_hands.iter().map(|h| h.bid).collect();
+        let bids: Vec<u64> = hands.iter().map(|h| h.bid).collect();
+        assert_eq!(vec_bids, bids);
+        println!("Vec hands: {:?}, packed hands: {:?}", vec_time, packed_time);
+    }
+
     #[test]
     fn part_1() {
         let input = load_input("example");
@@ -1,5 +1,5 @@
 use regex::Regex;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
@@ -9,14 +9,38 @@ This whole solution only works if each of the "paths":
 - encounters only 1 end node
 - enters a loop
 - the distance from the start node to the end node is the same as from the end node to the start of the loop
+part_2 checks these for every path and returns an error instead of a wrong answer
+
+part_2_general doesn't rely on any of that, it finds the cycle of every path and combines them with the CRT
 */
 
 type Mapping = HashMap<String, (String, String)>;
 
 fn main() {
     let (moves, maps) = load_input("input");
+    if let Some("dot") = std::env::args().nth(1).as_deref() {
+        let highlight = std::env::args().nth(2).as_deref() == Some("cycles");
+        print!("{}", to_dot(&moves, &maps, highlight));
+        return;
+    }
     println!("Solution for part 1: {}", part_1(&moves, &maps));
-    println!("Solution for part 2: {}", part_2(&moves, &maps));
+    match part_2(&moves, &maps) {
+        Ok(steps) => println!("Solution for part 2: {}", steps),
+        Err(e) => println!("Can't use the lcm for part 2: {}", e),
+    }
+    let network = Network::new(&moves, &maps);
+    println!(
+        "Solution for part 1 using interned nodes: {}",
+        part_1_interned(&network)
+    );
+    match part_2_interned(&network) {
+        Ok(steps) => println!("Solution for part 2 using interned nodes: {}", steps),
+        Err(e) => println!("Can't use the lcm for part 2 with interned nodes: {}", e),
+    }
+    match part_2_general(&moves, &maps) {
+        Ok(steps) => println!("Solution for part 2 using cycle analysis: {}", steps),
+        Err(e) => println!("Cycle analysis failed: {}", e),
+    }
 }
 
 fn part_1(moves: &[char], maps: &Mapping) -> usize {
@@ -32,8 +56,8 @@ fn part_1(moves: &[char], maps: &Mapping) -> usize {
     counter
 }
 
-fn part_2(moves: &[char], maps: &Mapping) -> usize {
-    let loops: Vec<usize> = maps
+fn part_2(moves: &[char], maps: &Mapping) -> Result<usize, ShortcutError> {
+    let mut starts: Vec<&str> = maps
         .keys()
         .filter_map(|k| {
             if k.ends_with('A') {
@@ -42,13 +66,157 @@ fn part_2(moves: &[char], maps: &Mapping) -> usize {
                 None
             }
         })
-        .map(|x| get_loop_len(moves, maps, x))
         .collect();
+    starts.sort_unstable();
     let mut cur_lcm = 1;
-    for l in loops {
+    for start in starts {
+        let l = check_shortcut(moves, maps, start)?;
         cur_lcm = lcm(cur_lcm, l);
     }
-    cur_lcm
+    Ok(cur_lcm)
+}
+
+/// One of the assumptions from the top of the file doesn't hold for the path of `start`
+#[derive(Debug, PartialEq, Eq)]
+enum ShortcutError {
+    NoEndNode {
+        start: String,
+    },
+    MultipleEndNodes {
+        start: String,
+        ends: Vec<String>,
+    },
+    NoLoop {
+        start: String,
+        end_step: usize,
+        loop_start: usize,
+    },
+    OffsetMismatch {
+        start: String,
+        offset: usize,
+        loop_len: usize,
+    },
+    IrregularHits {
+        start: String,
+        hits: Vec<usize>,
+        cycle_len: usize,
+    },
+}
+
+impl std::fmt::Display for ShortcutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShortcutError::NoEndNode { start } => {
+                write!(f, "path from {} never reaches an end node", start)
+            }
+            ShortcutError::MultipleEndNodes { start, ends } => write!(
+                f,
+                "path from {} reaches {} end nodes: {}",
+                start,
+                ends.len(),
+                ends.join(", ")
+            ),
+            ShortcutError::NoLoop {
+                start,
+                end_step,
+                loop_start,
+            } => write!(
+                f,
+                "path from {} reaches its end node at step {} but the loop only starts at step {}",
+                start, end_step, loop_start
+            ),
+            ShortcutError::OffsetMismatch {
+                start,
+                offset,
+                loop_len,
+            } => write!(
+                f,
+                "path from {} takes {} steps to the end node but the loop is {} steps long",
+                start, offset, loop_len
+            ),
+            ShortcutError::IrregularHits {
+                start,
+                hits,
+                cycle_len,
+            } => write!(
+                f,
+                "path from {} reaches its end node at steps {} but repeats every {} steps",
+                start,
+                hits.iter()
+                    .map(|h| h.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                cycle_len
+            ),
+        }
+    }
+}
+
+/// Returns the loop length if the path from `start` fits the assumptions needed for the lcm
+fn check_shortcut(moves: &[char], maps: &Mapping, start: &str) -> Result<usize, ShortcutError> {
+    check_cycle(&analyze_cycle(moves, maps, start), || {
+        get_loop_len(moves, maps, start)
+    })
+}
+
+/// Same as `check_shortcut` for an already analyzed path, `loop_len` is only called once an end node is known to be in the loop
+fn check_cycle(
+    cycle: &CycleInfo,
+    loop_len: impl FnOnce() -> usize,
+) -> Result<usize, ShortcutError> {
+    let start = cycle.start.as_str();
+    let mut ends: Vec<String> = cycle.end_hits.iter().map(|h| h.node.clone()).collect();
+    ends.sort_unstable();
+    ends.dedup();
+    match ends.len() {
+        0 => {
+            return Err(ShortcutError::NoEndNode {
+                start: start.to_string(),
+            })
+        }
+        1 => (),
+        _ => {
+            return Err(ShortcutError::MultipleEndNodes {
+                start: start.to_string(),
+                ends,
+            })
+        }
+    }
+
+    let last_hit = cycle.end_hits.last().unwrap();
+    if last_hit.step < cycle.offset {
+        return Err(ShortcutError::NoLoop {
+            start: start.to_string(),
+            end_step: last_hit.step,
+            loop_start: cycle.offset,
+        });
+    }
+
+    let offset = cycle.end_hits[0].step;
+    let loop_len = loop_len();
+    if offset != loop_len {
+        return Err(ShortcutError::OffsetMismatch {
+            start: start.to_string(),
+            offset,
+            loop_len,
+        });
+    }
+
+    // The end node has to come up at every multiple of the loop length and nowhere else.
+    // Checking up to where the path repeats is enough if the cycle is a whole number of loops,
+    // unless the path repeats from the start node, which is at a multiple of the loop length without being an end.
+    let hits: Vec<usize> = cycle.end_hits.iter().map(|h| h.step).collect();
+    let expected: Vec<usize> = (loop_len..cycle.offset + cycle.length)
+        .step_by(loop_len)
+        .collect();
+    if cycle.offset == 0 || !cycle.length.is_multiple_of(loop_len) || hits != expected {
+        return Err(ShortcutError::IrregularHits {
+            start: start.to_string(),
+            hits,
+            cycle_len: cycle.length,
+        });
+    }
+    Ok(loop_len)
 }
 
 // Least common multiple
@@ -91,6 +259,398 @@ fn get_loop_len(moves: &[char], maps: &Mapping, start: &str) -> usize {
     loop_len.unwrap()
 }
 
+#[derive(Debug, PartialEq, Eq)]
+enum GhostError {
+    NoStartNodes,
+    NoCommonStep,
+}
+
+impl std::fmt::Display for GhostError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GhostError::NoStartNodes => write!(f, "no start nodes"),
+            GhostError::NoCommonStep => write!(f, "the paths never end at the same step"),
+        }
+    }
+}
+
+/// An end node reached at `step`, `instruction` is the index of the next move
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct EndHit {
+    node: String,
+    instruction: usize,
+    step: usize,
+}
+
+/// Path of a single start node, a state is a node together with the instruction index.
+/// After `offset` steps the path enters a cycle of `length` steps that repeats forever.
+#[derive(Debug, PartialEq, Eq)]
+struct CycleInfo {
+    start: String,
+    offset: usize,
+    length: usize,
+    /// Every end node before the path repeats, sorted by step
+    end_hits: Vec<EndHit>,
+}
+
+impl CycleInfo {
+    fn is_end_at(&self, step: usize) -> bool {
+        if step < self.offset {
+            self.end_hits.iter().any(|h| h.step == step)
+        } else {
+            let in_cycle = self.offset + (step - self.offset) % self.length;
+            self.end_hits.iter().any(|h| h.step == in_cycle)
+        }
+    }
+
+    /// Steps of the hits that repeat every `length` steps
+    fn cycle_hits(&self) -> impl Iterator<Item = usize> + '_ {
+        self.end_hits
+            .iter()
+            .filter(|h| h.step >= self.offset)
+            .map(|h| h.step)
+    }
+}
+
+fn analyze_cycle(moves: &[char], maps: &Mapping, start: &str) -> CycleInfo {
+    let mut seen: HashMap<(&str, usize), usize> = HashMap::new();
+    let mut end_hits = Vec::new();
+    let mut cur_node = start;
+    let mut step = 0;
+    loop {
+        let instruction = step % moves.len();
+        if let Some(first) = seen.insert((cur_node, instruction), step) {
+            return CycleInfo {
+                start: start.to_string(),
+                offset: first,
+                length: step - first,
+                end_hits,
+            };
+        }
+        if cur_node.ends_with('Z') {
+            end_hits.push(EndHit {
+                node: cur_node.to_string(),
+                instruction,
+                step,
+            });
+        }
+        cur_node = get_next_node(&moves[instruction], maps, cur_node);
+        step += 1;
+    }
+}
+
+fn part_2_general(moves: &[char], maps: &Mapping) -> Result<usize, GhostError> {
+    let mut starts: Vec<&str> = maps
+        .keys()
+        .filter(|k| k.ends_with('A'))
+        .map(|k| k.as_str())
+        .collect();
+    starts.sort_unstable();
+    if starts.is_empty() {
+        return Err(GhostError::NoStartNodes);
+    }
+    let cycles: Vec<CycleInfo> = starts
+        .iter()
+        .map(|s| analyze_cycle(moves, maps, s))
+        .collect();
+
+    // Hits before a path enters its cycle only happen once so they can simply be checked against every path
+    let mut best = cycles
+        .iter()
+        .flat_map(|c| c.end_hits.iter().filter(|h| h.step < c.offset))
+        .map(|h| h.step)
+        .filter(|step| cycles.iter().all(|c| c.is_end_at(*step)))
+        .min();
+
+    // Every combination of the repeating hits is a system of congruences
+    let min_step = cycles.iter().map(|c| c.offset).max().unwrap();
+    let mut congruences: HashSet<(i128, i128)> = HashSet::from([(0, 1)]);
+    for cycle in &cycles {
+        let mut next = HashSet::new();
+        for &(a, m) in &congruences {
+            for hit in cycle.cycle_hits() {
+                if let Some(c) = crt(a, m, hit as i128, cycle.length as i128) {
+                    next.insert(c);
+                }
+            }
+        }
+        congruences = next;
+    }
+    for (a, m) in congruences {
+        // Smallest number >= min_step that is a mod m
+        let min_step = min_step as i128;
+        let step = min_step + (a - min_step).rem_euclid(m);
+        let step = step as usize;
+        best = Some(best.map_or(step, |b| b.min(step)));
+    }
+    best.ok_or(GhostError::NoCommonStep)
+}
+
+/// Combines x = a1 (mod m1) and x = a2 (mod m2) into x = a (mod lcm(m1, m2)) if there is a solution
+/// The moduli don't need to be coprime
+fn crt(a1: i128, m1: i128, a2: i128, m2: i128) -> Option<(i128, i128)> {
+    let (g, p, _) = extended_gcd(m1, m2);
+    if (a2 - a1) % g != 0 {
+        return None;
+    }
+    let lcm = m1 / g * m2;
+    let k = ((a2 - a1) / g % (m2 / g) * p).rem_euclid(m2 / g);
+    Some(((a1 + m1 * k).rem_euclid(lcm), lcm))
+}
+
+/// Returns (gcd, x, y) such that a * x + b * y = gcd
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, x, y) = extended_gcd(b, a % b);
+        (g, y, x - (a / b) * y)
+    }
+}
+
+/// The same network as `Mapping` but with every node turned into an index
+struct Network {
+    names: Vec<String>,
+    ids: HashMap<String, u16>,
+    /// Left and right node of every node
+    children: Vec<(u16, u16)>,
+    /// `true` for a right move
+    moves: Vec<bool>,
+}
+
+impl Network {
+    fn new(moves: &[char], maps: &Mapping) -> Self {
+        let mut names: Vec<String> = maps.keys().cloned().collect();
+        names.sort_unstable();
+        assert!(names.len() <= u16::MAX as usize + 1, "Too many nodes");
+        let ids: HashMap<String, u16> = names
+            .iter()
+            .enumerate()
+            .map(|(i, n)| (n.clone(), i as u16))
+            .collect();
+        let children = names
+            .iter()
+            .map(|n| {
+                let (left, right) = &maps[n];
+                (ids[left], ids[right])
+            })
+            .collect();
+        Self {
+            names,
+            ids,
+            children,
+            moves: moves.iter().map(|m| *m == 'R').collect(),
+        }
+    }
+
+    fn step(&self, node: u16, instruction: usize) -> u16 {
+        let (left, right) = self.children[node as usize];
+        if self.moves[instruction] {
+            right
+        } else {
+            left
+        }
+    }
+
+    fn jump_table(&self, is_end: impl Fn(&str) -> bool) -> JumpTable {
+        let is_end: Vec<bool> = self.names.iter().map(|n| is_end(n)).collect();
+        let mut after_pass = Vec::with_capacity(self.names.len());
+        let mut ends = Vec::with_capacity(self.names.len());
+        for start in 0..self.names.len() as u16 {
+            let mut node = start;
+            let mut pass_ends = Vec::new();
+            for i in 0..self.moves.len() {
+                if is_end[node as usize] {
+                    pass_ends.push((i, node));
+                }
+                node = self.step(node, i);
+            }
+            after_pass.push(node);
+            ends.push(pass_ends);
+        }
+        JumpTable {
+            pass_len: self.moves.len(),
+            is_end,
+            after_pass,
+            ends,
+        }
+    }
+}
+
+/// Where every node ends up after a full pass of the moves and where in the pass it is on an end node
+struct JumpTable {
+    pass_len: usize,
+    is_end: Vec<bool>,
+    after_pass: Vec<u16>,
+    /// Index into the pass and the end node for every end node hit during a pass starting at the node
+    ends: Vec<Vec<(usize, u16)>>,
+}
+
+impl JumpTable {
+    /// Steps until an end node is reached from `node` at the start of a pass, including the node itself.
+    /// `None` if no end node is ever reached.
+    fn steps_to_end(&self, node: u16) -> Option<usize> {
+        let mut node = node;
+        // After as many passes as there are nodes the pass start nodes have to repeat
+        for pass in 0..self.after_pass.len() {
+            if let Some((i, _)) = self.ends[node as usize].first() {
+                return Some(pass * self.pass_len + i);
+            }
+            node = self.after_pass[node as usize];
+        }
+        None
+    }
+}
+
+fn part_1_interned(network: &Network) -> usize {
+    let table = network.jump_table(|n| n == "ZZZ");
+    table.steps_to_end(network.ids["AAA"]).unwrap()
+}
+
+/// Same checks as `part_2`
+fn part_2_interned(network: &Network) -> Result<usize, ShortcutError> {
+    let table = network.jump_table(|n| n.ends_with('Z'));
+    let mut cur_lcm = 1;
+    for name in network.names.iter().filter(|n| n.ends_with('A')) {
+        let start = network.ids[name];
+        let cycle = analyze_passes(network, &table, start);
+        let l = check_cycle(&cycle, || get_loop_len_interned(network, &table, start))?;
+        cur_lcm = lcm(cur_lcm, l);
+    }
+    Ok(cur_lcm)
+}
+
+/// Same as `analyze_cycle` but only looks at the node at the start of every pass,
+/// which has to repeat within as many passes as there are nodes.
+///
+/// The loop it finds can start up to a pass later than the one `analyze_cycle` finds.
+/// Every state from there on still repeats, which is all `check_cycle` needs.
+fn analyze_passes(network: &Network, table: &JumpTable, start: u16) -> CycleInfo {
+    let pass_len = table.pass_len;
+    let mut seen: Vec<Option<usize>> = vec![None; network.names.len()];
+    let mut end_hits = Vec::new();
+    let mut node = start;
+    let mut pass = 0;
+    loop {
+        if let Some(first) = seen[node as usize] {
+            return CycleInfo {
+                start: network.names[start as usize].clone(),
+                offset: first * pass_len,
+                length: (pass - first) * pass_len,
+                end_hits,
+            };
+        }
+        seen[node as usize] = Some(pass);
+        for (instruction, end) in &table.ends[node as usize] {
+            end_hits.push(EndHit {
+                node: network.names[*end as usize].clone(),
+                instruction: *instruction,
+                step: pass * pass_len + instruction,
+            });
+        }
+        node = table.after_pass[node as usize];
+        pass += 1;
+    }
+}
+
+/// Same as `get_loop_len` but jumps a whole pass at a time.
+/// Like `get_loop_len` this never returns if the path doesn't reach an end node twice, `check_cycle` rules that out first.
+fn get_loop_len_interned(network: &Network, table: &JumpTable, start: u16) -> usize {
+    let first = table
+        .steps_to_end(start)
+        .expect("Path should reach an end node");
+    let pass_len = network.moves.len();
+    // Walk to the end node and then by hand to the end of that pass since the table only works from the start of one
+    let mut node = start;
+    for _ in 0..first / pass_len {
+        node = table.after_pass[node as usize];
+    }
+    for i in 0..first % pass_len {
+        node = network.step(node, i);
+    }
+    let mut count = 0;
+    let mut instruction = first % pass_len;
+    loop {
+        node = network.step(node, instruction);
+        count += 1;
+        instruction = (instruction + 1) % pass_len;
+        if instruction == 0 {
+            break;
+        }
+        if table.is_end[node as usize] {
+            return count;
+        }
+    }
+    count
+        + table
+            .steps_to_end(node)
+            .expect("Path should reach the end node again")
+}
+
+const CYCLE_COLORS: [&str; 6] = ["blue", "orange", "purple", "darkgreen", "brown", "magenta"];
+
+/// Graphviz graph of the network, with `highlight` the cycle of every start node gets its own colour
+fn to_dot(moves: &[char], maps: &Mapping, highlight: bool) -> String {
+    let mut nodes: Vec<&String> = maps.keys().collect();
+    nodes.sort_unstable();
+
+    let mut colored: HashMap<(&str, char), Vec<&str>> = HashMap::new();
+    if highlight {
+        let starts = nodes.iter().filter(|n| n.ends_with('A'));
+        for (start, color) in starts.zip(CYCLE_COLORS.iter().cycle()) {
+            for edge in cycle_edges(moves, maps, start) {
+                colored.entry(edge).or_default().push(color);
+            }
+        }
+    }
+
+    // IDs are quoted since Graphviz would read a name like 11A as the number 11 followed by A
+    let mut dot = String::from("digraph network {\n");
+    for node in &nodes {
+        if node.ends_with('A') {
+            dot.push_str(&format!(
+                "    \"{}\" [shape=box, style=filled, fillcolor=palegreen];\n",
+                node
+            ));
+        } else if node.ends_with('Z') {
+            dot.push_str(&format!(
+                "    \"{}\" [shape=doublecircle, style=filled, fillcolor=salmon];\n",
+                node
+            ));
+        }
+    }
+    for node in &nodes {
+        let (left, right) = &maps[*node];
+        for (dir, target) in [('L', left), ('R', right)] {
+            let style = match colored.get(&(node.as_str(), dir)) {
+                Some(colors) => format!(", color=\"{}\", penwidth=2", colors.join(":")),
+                None => String::new(),
+            };
+            dot.push_str(&format!(
+                "    \"{}\" -> \"{}\" [label={}{}];\n",
+                node, target, dir, style
+            ));
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// Every edge taken inside the cycle of `start`
+fn cycle_edges<'a>(moves: &[char], maps: &'a Mapping, start: &str) -> HashSet<(&'a str, char)> {
+    let cycle = analyze_cycle(moves, maps, start);
+    let mut edges = HashSet::new();
+    let mut cur_node = maps.get_key_value(start).unwrap().0.as_str();
+    for step in 0..cycle.offset + cycle.length {
+        let m = moves[step % moves.len()];
+        if step >= cycle.offset {
+            edges.insert((cur_node, m));
+        }
+        cur_node = get_next_node(&m, maps, cur_node);
+    }
+    edges
+}
+
 fn get_next_node<'a>(m: &char, maps: &'a Mapping, cur_node: &str) -> &'a str {
     let targets = maps.get(cur_node).unwrap();
     match *m {
@@ -123,15 +683,304 @@ fn load_input(name: &str) -> (Vec<char>, Mapping) {
 mod tests {
     use super::*;
 
+    fn mapping(nodes: &[(&str, &str, &str)]) -> Mapping {
+        nodes
+            .iter()
+            .map(|(n, l, r)| (n.to_string(), (l.to_string(), r.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn general() {
+        let moves: Vec<char> = "LR".chars().collect();
+        let maps = mapping(&[
+            ("11A", "11B", "XXX"),
+            ("11B", "XXX", "11Z"),
+            ("11Z", "11B", "XXX"),
+            ("22A", "22B", "XXX"),
+            ("22B", "22C", "22C"),
+            ("22C", "22Z", "22Z"),
+            ("22Z", "22B", "22B"),
+            ("XXX", "XXX", "XXX"),
+        ]);
+        assert_eq!(part_2_general(&moves, &maps), Ok(6));
+
+        // The end node is only hit before the loop on 11A, lcm would get this wrong
+        let moves = vec!['L'];
+        let maps = mapping(&[
+            ("11A", "11Z", "11Z"),
+            ("11Z", "XXX", "XXX"),
+            ("22A", "22Z", "22Z"),
+            ("22Z", "22A", "22A"),
+            ("XXX", "XXX", "XXX"),
+        ]);
+        assert_eq!(part_2_general(&moves, &maps), Ok(1));
+        assert_eq!(
+            crate::part_2(&moves, &maps),
+            Err(ShortcutError::NoLoop {
+                start: "11A".to_string(),
+                end_step: 1,
+                loop_start: 2
+            })
+        );
+
+        // Offset isn't the same as the loop length
+        let maps = mapping(&[
+            ("11A", "11B", "11B"),
+            ("11B", "11C", "11C"),
+            ("11C", "11Z", "11Z"),
+            ("11Z", "11C", "11C"),
+            ("22A", "22Z", "22Z"),
+            ("22Z", "22A", "22A"),
+        ]);
+        let cycle = analyze_cycle(&moves, &maps, "11A");
+        assert_eq!((cycle.offset, cycle.length), (2, 2));
+        assert_eq!(part_2_general(&moves, &maps), Ok(3));
+        assert_eq!(
+            crate::part_2(&moves, &maps),
+            Err(ShortcutError::OffsetMismatch {
+                start: "11A".to_string(),
+                offset: 3,
+                loop_len: 2
+            })
+        );
+
+        let maps = mapping(&[
+            ("11A", "21Z", "21Z"),
+            ("21Z", "11Z", "11Z"),
+            ("11Z", "11A", "11A"),
+        ]);
+        let e = crate::part_2(&moves, &maps).unwrap_err();
+        assert_eq!(e.to_string(), "path from 11A reaches 2 end nodes: 11Z, 21Z");
+
+        // One path is on an end node every odd step, the other every even step
+        let maps = mapping(&[
+            ("11A", "11Z", "11Z"),
+            ("11Z", "11A", "11A"),
+            ("22A", "22B", "22B"),
+            ("22B", "22Z", "22Z"),
+            ("22Z", "22B", "22B"),
+        ]);
+        assert_eq!(part_2_general(&moves, &maps), Err(GhostError::NoCommonStep));
+
+        // 11Z is hit at steps 2, 4, 8, 10, ... so the gap between the first hits isn't a period
+        let moves: Vec<char> = "LLR".chars().collect();
+        let maps = mapping(&[
+            ("11A", "11B", "11B"),
+            ("11B", "11Z", "11Z"),
+            ("11Z", "11D", "11C"),
+            ("11C", "11Z", "11Z"),
+            ("11D", "11A", "11A"),
+            ("22A", "22B", "22B"),
+            ("22B", "22C", "22C"),
+            ("22C", "22Z", "22Z"),
+            ("22Z", "22B", "22B"),
+        ]);
+        assert_eq!(part_2_general(&moves, &maps), Err(GhostError::NoCommonStep));
+        assert_eq!(
+            crate::part_2(&moves, &maps),
+            Err(ShortcutError::IrregularHits {
+                start: "11A".to_string(),
+                hits: vec![2, 4],
+                cycle_len: 6
+            })
+        );
+
+        // Same hits as above but the path doesn't go back to the start node
+        let maps = mapping(&[
+            ("11A", "11B", "11B"),
+            ("11B", "11Z", "11Z"),
+            ("11Z", "11D", "11C"),
+            ("11C", "11Z", "11Z"),
+            ("11D", "11E", "11E"),
+            ("11E", "11B", "11B"),
+            ("22A", "22Z", "22Z"),
+            ("22Z", "22A", "22A"),
+        ]);
+        let cycle = analyze_cycle(&moves, &maps, "11A");
+        assert_eq!((cycle.offset, cycle.length), (1, 6));
+        assert_eq!(
+            crate::part_2(&moves, &maps),
+            Err(ShortcutError::IrregularHits {
+                start: "11A".to_string(),
+                hits: vec![2, 4],
+                cycle_len: 6
+            })
+        );
+    }
+
+    /// Same as `analyze_cycle` but keeps the seen states in a flat table instead of hashing node names
+    fn analyze_cycle_interned(network: &Network, table: &JumpTable, start: u16) -> CycleInfo {
+        let pass_len = network.moves.len();
+        let mut seen: Vec<Option<usize>> = vec![None; network.names.len() * pass_len];
+        let mut end_hits = Vec::new();
+        let mut node = start;
+        let mut step = 0;
+        loop {
+            let instruction = step % pass_len;
+            let state = node as usize * pass_len + instruction;
+            if let Some(first) = seen[state] {
+                return CycleInfo {
+                    start: network.names[start as usize].clone(),
+                    offset: first,
+                    length: step - first,
+                    end_hits,
+                };
+            }
+            seen[state] = Some(step);
+            if table.is_end[node as usize] {
+                end_hits.push(EndHit {
+                    node: network.names[node as usize].clone(),
+                    instruction,
+                    step,
+                });
+            }
+            node = network.step(node, instruction);
+            step += 1;
+        }
+    }
+
+    #[test]
+    fn interned() {
+        let moves: Vec<char> = "LLR".chars().collect();
+        let maps = mapping(&[
+            ("AAA", "BBB", "BBB"),
+            ("BBB", "AAA", "ZZZ"),
+            ("ZZZ", "ZZZ", "ZZZ"),
+        ]);
+        let network = Network::new(&moves, &maps);
+        assert_eq!(part_1_interned(&network), crate::part_1(&moves, &maps));
+
+        let moves: Vec<char> = "LR".chars().collect();
+        let maps = mapping(&[
+            ("11A", "11B", "XXX"),
+            ("11B", "XXX", "11Z"),
+            ("11Z", "11B", "XXX"),
+            ("22A", "22B", "XXX"),
+            ("22B", "22C", "22C"),
+            ("22C", "22Z", "22Z"),
+            ("22Z", "22B", "22B"),
+            ("XXX", "XXX", "XXX"),
+        ]);
+        let network = Network::new(&moves, &maps);
+        let table = network.jump_table(|n| n.ends_with('Z'));
+        for start in ["11A", "22A"] {
+            assert_eq!(
+                get_loop_len_interned(&network, &table, network.ids[start]),
+                get_loop_len(&moves, &maps, start)
+            );
+        }
+        assert_eq!(part_2_interned(&network), crate::part_2(&moves, &maps));
+        for start in ["11A", "22A"] {
+            assert_eq!(
+                analyze_cycle_interned(&network, &table, network.ids[start]),
+                analyze_cycle(&moves, &maps, start)
+            );
+        }
+
+        // Random networks where the pass level analysis has to agree with walking every step
+        let mut seed: u64 = 0x2023_0008;
+        let mut random = |max: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % max
+        };
+        for _ in 0..500 {
+            let moves: Vec<char> = (0..1 + random(4))
+                .map(|_| if random(2) == 0 { 'L' } else { 'R' })
+                .collect();
+            let names = ["11A", "22A", "11B", "22B", "33C", "11Z", "22Z"];
+            let size = 3 + random(5) as usize;
+            let nodes: Vec<(&str, &str, &str)> = names[..size]
+                .iter()
+                .map(|n| {
+                    (
+                        *n,
+                        names[random(size as u64) as usize],
+                        names[random(size as u64) as usize],
+                    )
+                })
+                .collect();
+            let maps = mapping(&nodes);
+            let network = Network::new(&moves, &maps);
+            let table = network.jump_table(|n| n.ends_with('Z'));
+            for start in names[..size].iter().filter(|n| n.ends_with('A')) {
+                let id = network.ids[*start];
+                let passes = analyze_passes(&network, &table, id);
+                let steps = analyze_cycle_interned(&network, &table, id);
+                assert_eq!(passes.length, steps.length);
+                assert!(passes.offset >= steps.offset);
+                assert!(passes.offset < steps.offset + moves.len());
+                let check = |cycle: &CycleInfo| {
+                    check_cycle(cycle, || get_loop_len(&moves, &maps, start)).ok()
+                };
+                assert_eq!(check(&passes), check(&steps));
+                assert_eq!(check(&steps), check_shortcut(&moves, &maps, start).ok());
+            }
+        }
+
+        // Paths that break the lcm assumptions get the same error as `part_2`
+        let moves: Vec<char> = "LLR".chars().collect();
+        let maps = mapping(&[
+            ("11A", "11B", "11B"),
+            ("11B", "11Z", "11Z"),
+            ("11Z", "11D", "11C"),
+            ("11C", "11Z", "11Z"),
+            ("11D", "11A", "11A"),
+            ("22A", "22B", "22B"),
+            ("22B", "22C", "22C"),
+            ("22C", "22Z", "22Z"),
+            ("22Z", "22B", "22B"),
+        ]);
+        let network = Network::new(&moves, &maps);
+        assert!(part_2_interned(&network).is_err());
+        assert_eq!(part_2_interned(&network), crate::part_2(&moves, &maps));
+        let maps = mapping(&[("11A", "11B", "11B"), ("11B", "11A", "11A")]);
+        assert_eq!(
+            part_2_interned(&Network::new(&moves, &maps)),
+            Err(ShortcutError::NoEndNode {
+                start: "11A".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn dot() {
+        let moves = vec!['L'];
+        let maps = mapping(&[
+            ("11A", "11Z", "11A"),
+            ("11Z", "11A", "11Z"),
+            ("XXX", "XXX", "XXX"),
+        ]);
+        assert_eq!(
+            to_dot(&moves, &maps, true),
+            "digraph network {
+    \"11A\" [shape=box, style=filled, fillcolor=palegreen];
+    \"11Z\" [shape=doublecircle, style=filled, fillcolor=salmon];
+    \"11A\" -> \"11Z\" [label=L, color=\"blue\", penwidth=2];
+    \"11A\" -> \"11A\" [label=R];
+    \"11Z\" -> \"11A\" [label=L, color=\"blue\", penwidth=2];
+    \"11Z\" -> \"11Z\" [label=R];
+    \"XXX\" -> \"XXX\" [label=L];
+    \"XXX\" -> \"XXX\" [label=R];
+}
+"
+        );
+        assert!(!to_dot(&moves, &maps, false).contains("penwidth"));
+    }
+
     #[test]
     fn part_1() {
         let (moves, maps) = load_input("example");
         assert_eq!(crate::part_1(&moves, &maps), 2);
+        assert_eq!(part_1_interned(&Network::new(&moves, &maps)), 2);
     }
 
     #[test]
     fn part_2() {
         let (moves, maps) = load_input("example2");
-        assert_eq!(crate::part_2(&moves, &maps), 6);
+        assert_eq!(crate::part_2(&moves, &maps), Ok(6));
+        assert_eq!(part_2_interned(&Network::new(&moves, &maps)), Ok(6));
     }
 }
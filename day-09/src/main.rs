@@ -4,39 +4,392 @@ use std::io::BufReader;
 
 fn main() {
     let input = load_input("input");
-    let results = solve(&input);
-    println!("Solution for part 1: {}", results.0);
-    println!("Solution for part 2: {}", results.1);
+    if let Some("extrapolate") = std::env::args().nth(1).as_deref() {
+        let steps = std::env::args()
+            .nth(2)
+            .and_then(|n| n.parse::<i128>().ok())
+            .expect("Usage: extrapolate <steps> [error|skip|least-squares=<degree>]");
+        let fallback = match std::env::args().nth(3).as_deref() {
+            None | Some("error") => Fallback::Error,
+            Some("skip") => Fallback::Skip,
+            Some(other) => Fallback::LeastSquares(
+                other
+                    .strip_prefix("least-squares=")
+                    .and_then(|d| d.parse().ok())
+                    .expect("Unknown fallback"),
+            ),
+        };
+        for (i, history) in input.iter().enumerate() {
+            let fit = match fit_with(history, fallback) {
+                Ok(Some(fit)) => fit,
+                Ok(None) => {
+                    println!("line {} skipped", i + 1);
+                    continue;
+                }
+                Err(e) => {
+                    println!("line {}: {}", i + 1, e);
+                    continue;
+                }
+            };
+            let coefficients = fit.coefficients().map_or("overflow".to_string(), |c| {
+                let c: Vec<String> = c.iter().map(|c| c.to_string()).collect();
+                format!("[{}]", c.join(", "))
+            });
+            let show =
+                |value: Option<Ratio>| value.map_or("overflow".to_string(), |v| v.to_string());
+            println!(
+                "line {} {}degree {} coefficients {} {} steps back: {} forward: {}",
+                i + 1,
+                if fit.is_exact() { "" } else { "approximate " },
+                fit.degree(),
+                coefficients,
+                steps,
+                show(fit.backward(steps)),
+                show(fit.forward(steps))
+            );
+        }
+        return;
+    }
+    match solve(&input) {
+        Ok(results) => {
+            println!("Solution for part 1: {}", results.0);
+            println!("Solution for part 2: {}", results.1);
+        }
+        Err((i, e)) => println!("Can't extrapolate line {}: {}", i + 1, e),
+    }
 }
 
-fn solve(input: &[Vec<isize>]) -> (isize, isize) {
+/// Sum of the next and of the previous value of every history.
+/// Fails with the index of the first history that isn't a polynomial or whose sums overflow.
+fn solve(input: &[Vec<isize>]) -> Result<(isize, isize), (usize, FitError)> {
     let mut result_part_1 = 0;
     let mut result_part_2 = 0;
 
-    for history in input {
-        let simulation: Vec<Vec<isize>> = calculate_differences(history);
-        let mut prev_last_value = 0;
-        let mut prev_first_value = 0;
-        for row in simulation.iter().rev() {
-            prev_last_value += *row.last().unwrap();
-            prev_first_value = row[0] - prev_first_value;
+    for (i, history) in input.iter().enumerate() {
+        let polynomial = Polynomial::fit(history).map_err(|e| (i, e))?;
+        let add = |sum: isize, value: Option<i128>| {
+            value
+                .and_then(|v| isize::try_from(v).ok())
+                .and_then(|v| sum.checked_add(v))
+                .ok_or((i, FitError::Overflow))
+        };
+        result_part_1 = add(result_part_1, polynomial.value_at(history.len() as i128))?;
+        result_part_2 = add(result_part_2, polynomial.value_at(-1))?;
+    }
+    Ok((result_part_1, result_part_2))
+}
+
+/// The polynomial going through every value of a history, with x being the index of the value
+#[derive(Debug, PartialEq, Eq)]
+struct Polynomial {
+    /// First value of every row of the difference pyramid, until the all zero row
+    leading_differences: Vec<i128>,
+    len: usize,
+}
+
+impl Polynomial {
+    /// Only works if the differences reach a constant row while there are still at least 2 values in it,
+    /// otherwise there is no way to tell whether the history is really a polynomial.
+    fn fit(history: &[isize]) -> Result<Self, FitError> {
+        let mut row: Vec<i128> = history.iter().map(|x| *x as i128).collect();
+        let mut leading_differences = Vec::new();
+        while row.is_empty() || !row.iter().all(|x| *x == 0) {
+            if row.len() < 2 {
+                return Err(FitError::NotPolynomial { len: history.len() });
+            }
+            leading_differences.push(row[0]);
+            row = row.windows(2).map(|w| w[1] - w[0]).collect();
         }
-        result_part_1 += prev_last_value;
-        result_part_2 += prev_first_value;
+        Ok(Self {
+            leading_differences,
+            len: history.len(),
+        })
+    }
+
+    fn degree(&self) -> usize {
+        self.leading_differences.len().saturating_sub(1)
+    }
+
+    /// Newton's forward difference formula, sum of Δ^k * (x choose k).
+    /// `None` if it overflows.
+    fn value_at(&self, x: i128) -> Option<i128> {
+        let mut result: i128 = 0;
+        let mut binomial: i128 = 1;
+        for (k, difference) in self.leading_differences.iter().enumerate() {
+            result = result.checked_add(difference.checked_mul(binomial)?)?;
+            // (x choose k + 1) = (x choose k) * (x - k) / (k + 1) which always divides evenly
+            binomial = binomial.checked_mul(x - k as i128)? / (k as i128 + 1);
+        }
+        Some(result)
+    }
+
+    /// Coefficients starting from x^0, `None` if they overflow
+    fn coefficients(&self) -> Option<Vec<Ratio>> {
+        let mut coefficients = vec![Ratio::from(0); self.degree() + 1];
+        // x * (x - 1) * ... * (x - k + 1) with integer coefficients
+        let mut falling: Vec<i128> = vec![1];
+        let mut factorial: i128 = 1;
+        for (k, difference) in self.leading_differences.iter().enumerate() {
+            for (i, c) in falling.iter().enumerate() {
+                let term = Ratio::checked_new(difference.checked_mul(*c)?, factorial)?;
+                coefficients[i] = coefficients[i].checked_add(term)?;
+            }
+            let mut next = vec![0_i128; falling.len() + 1];
+            for (i, c) in falling.iter().enumerate() {
+                next[i + 1] = next[i + 1].checked_add(*c)?;
+                next[i] = next[i].checked_sub(c.checked_mul(k as i128)?)?;
+            }
+            falling = next;
+            factorial = factorial.checked_mul(k as i128 + 1)?;
+        }
+        Some(coefficients)
     }
-    (result_part_1, result_part_2)
 }
 
-fn calculate_differences(history: &[isize]) -> Vec<Vec<isize>> {
-    let mut simulation: Vec<Vec<isize>> = vec![history.to_vec()];
-    while !simulation.last().unwrap().iter().all(|x| *x == 0) {
-        let mut new_row: Vec<isize> = Vec::new();
-        for window in simulation.last().unwrap().windows(2) {
-            new_row.push(window[1] - window[0])
+#[derive(Debug, PartialEq, Eq)]
+enum FitError {
+    NotPolynomial { len: usize },
+    DegreeTooHigh { degree: usize, len: usize },
+    Overflow,
+}
+
+impl std::fmt::Display for FitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FitError::NotPolynomial { len } => write!(
+                f,
+                "the {} values never reach a constant row so they aren't a polynomial",
+                len
+            ),
+            FitError::DegreeTooHigh { degree, len } => write!(
+                f,
+                "can't fit a polynomial of degree {} to {} values with least squares",
+                degree, len
+            ),
+            FitError::Overflow => write!(f, "the values overflow"),
         }
-        simulation.push(new_row);
     }
-    simulation
+}
+
+/// What to do with a history that isn't a polynomial
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Fallback {
+    Error,
+    Skip,
+    /// Least-squares fit of the given degree
+    LeastSquares(usize),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Fit {
+    Exact(Polynomial),
+    Approximate(LeastSquares),
+}
+
+impl Fit {
+    fn is_exact(&self) -> bool {
+        matches!(self, Fit::Exact(_))
+    }
+
+    fn degree(&self) -> usize {
+        match self {
+            Fit::Exact(p) => p.degree(),
+            Fit::Approximate(l) => l.coefficients.len() - 1,
+        }
+    }
+
+    /// `None` if they overflow
+    fn coefficients(&self) -> Option<Vec<Ratio>> {
+        match self {
+            Fit::Exact(p) => p.coefficients(),
+            Fit::Approximate(l) => Some(l.coefficients.clone()),
+        }
+    }
+
+    fn value_at(&self, x: i128) -> Option<Ratio> {
+        match self {
+            Fit::Exact(p) => p.value_at(x).map(Ratio::from),
+            Fit::Approximate(l) => l.value_at(x),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Fit::Exact(p) => p.len,
+            Fit::Approximate(l) => l.len,
+        }
+    }
+
+    fn forward(&self, steps: i128) -> Option<Ratio> {
+        self.value_at(self.len() as i128 - 1 + steps)
+    }
+
+    fn backward(&self, steps: i128) -> Option<Ratio> {
+        self.value_at(-steps)
+    }
+}
+
+/// `Ok(None)` if the history was skipped
+fn fit_with(history: &[isize], fallback: Fallback) -> Result<Option<Fit>, FitError> {
+    match Polynomial::fit(history) {
+        Ok(p) => Ok(Some(Fit::Exact(p))),
+        Err(e) => match fallback {
+            Fallback::Error => Err(e),
+            Fallback::Skip => Ok(None),
+            Fallback::LeastSquares(degree) => {
+                LeastSquares::fit(history, degree).map(|l| Some(Fit::Approximate(l)))
+            }
+        },
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct LeastSquares {
+    /// Starting from x^0
+    coefficients: Vec<Ratio>,
+    len: usize,
+}
+
+impl LeastSquares {
+    /// Solves the normal equations exactly. The degree has to be lower than `len - 1`,
+    /// otherwise the history would be a polynomial already.
+    fn fit(history: &[isize], degree: usize) -> Result<Self, FitError> {
+        if degree + 1 >= history.len() {
+            return Err(FitError::DegreeTooHigh {
+                degree,
+                len: history.len(),
+            });
+        }
+        let size = degree + 1;
+        let coefficients = Self::solve_normal_equations(history, size).ok_or(FitError::Overflow)?;
+        Ok(Self {
+            coefficients,
+            len: history.len(),
+        })
+    }
+
+    /// `None` if any of the exact computations overflows
+    fn solve_normal_equations(history: &[isize], size: usize) -> Option<Vec<Ratio>> {
+        // Augmented matrix of (A^T * A | A^T * y) with A[i][j] = x_i^j
+        let mut matrix = vec![vec![Ratio::from(0); size + 1]; size];
+        for (x, y) in history.iter().enumerate() {
+            let powers = (0..size as u32)
+                .map(|p| (x as i128).checked_pow(p))
+                .collect::<Option<Vec<i128>>>()?;
+            for row in 0..size {
+                for col in 0..size {
+                    let term = Ratio::from(powers[row].checked_mul(powers[col])?);
+                    matrix[row][col] = matrix[row][col].checked_add(term)?;
+                }
+                let term = Ratio::from(powers[row].checked_mul(*y as i128)?);
+                matrix[row][size] = matrix[row][size].checked_add(term)?;
+            }
+        }
+
+        // Gaussian elimination, the matrix is always invertible for distinct x values
+        for col in 0..size {
+            let pivot = (col..size).find(|r| matrix[*r][col].num != 0).unwrap();
+            matrix.swap(col, pivot);
+            for row in 0..size {
+                if row != col && matrix[row][col].num != 0 {
+                    let factor = matrix[row][col].checked_div(matrix[col][col])?;
+                    let pivot_row = matrix[col].clone();
+                    for (target, source) in matrix[row][col..].iter_mut().zip(&pivot_row[col..]) {
+                        *target = target.checked_sub(factor.checked_mul(*source)?)?;
+                    }
+                }
+            }
+        }
+        (0..size)
+            .map(|i| matrix[i][size].checked_div(matrix[i][i]))
+            .collect()
+    }
+
+    fn value_at(&self, x: i128) -> Option<Ratio> {
+        let mut result = Ratio::from(0);
+        for c in self.coefficients.iter().rev() {
+            result = result.checked_mul(Ratio::from(x))?.checked_add(*c)?;
+        }
+        Some(result)
+    }
+}
+
+/// Always stored in lowest terms with a positive denominator
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+struct Ratio {
+    num: i128,
+    den: i128,
+}
+
+impl Ratio {
+    fn new(num: i128, den: i128) -> Self {
+        let g = gcd(num.abs(), den.abs()).max(1) * den.signum();
+        Self {
+            num: num / g,
+            den: den / g,
+        }
+    }
+}
+
+impl From<i128> for Ratio {
+    fn from(n: i128) -> Self {
+        Self { num: n, den: 1 }
+    }
+}
+
+impl Ratio {
+    /// Like `new` but `None` instead of overflowing while reducing
+    fn checked_new(num: i128, den: i128) -> Option<Self> {
+        num.checked_abs()?;
+        den.checked_abs()?;
+        Some(Self::new(num, den))
+    }
+
+    fn checked_add(self, other: Self) -> Option<Self> {
+        let num = self
+            .num
+            .checked_mul(other.den)?
+            .checked_add(other.num.checked_mul(self.den)?)?;
+        Self::checked_new(num, self.den.checked_mul(other.den)?)
+    }
+
+    fn checked_sub(self, other: Self) -> Option<Self> {
+        self.checked_add(Self::checked_new(other.num.checked_neg()?, other.den)?)
+    }
+
+    fn checked_mul(self, other: Self) -> Option<Self> {
+        Self::checked_new(
+            self.num.checked_mul(other.num)?,
+            self.den.checked_mul(other.den)?,
+        )
+    }
+
+    /// `None` when dividing by zero too
+    fn checked_div(self, other: Self) -> Option<Self> {
+        if other.num == 0 {
+            return None;
+        }
+        self.checked_mul(Self::new(other.den, other.num))
+    }
+}
+
+impl std::fmt::Display for Ratio {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.den == 1 {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
+
+fn gcd(mut big: i128, mut small: i128) -> i128 {
+    while small != 0 {
+        let tmp = small;
+        small = big % small;
+        big = tmp;
+    }
+    big
 }
 
 fn load_input(name: &str) -> Vec<Vec<isize>> {
@@ -57,15 +410,114 @@ fn load_input(name: &str) -> Vec<Vec<isize>> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn polynomial() {
+        let p = Polynomial::fit(&[10, 13, 16, 21, 30, 45]).unwrap();
+        assert_eq!(p.degree(), 3);
+        assert_eq!(p.value_at(6), Some(68));
+        assert_eq!(p.value_at(-1), Some(5));
+        for (x, y) in [10, 13, 16, 21, 30, 45].iter().enumerate() {
+            assert_eq!(p.value_at(x as i128), Some(*y));
+        }
+
+        let p = Polynomial::fit(&[1, 4, 9, 16]).unwrap();
+        assert_eq!(p.coefficients(), Some(vec![1.into(), 2.into(), 1.into()]));
+        let fit = Fit::Exact(p);
+        assert_eq!(fit.forward(3), Some(49.into()));
+        assert_eq!(fit.backward(3), Some(4.into()));
+
+        let p = Polynomial::fit(&[0, 1, 3, 6]).unwrap();
+        assert_eq!(
+            p.coefficients(),
+            Some(vec![0.into(), Ratio::new(1, 2), Ratio::new(1, 2)])
+        );
+        assert_eq!(p.coefficients().unwrap()[1].to_string(), "1/2");
+
+        // Would overflow an isize
+        let p = Polynomial::fit(&[0, isize::MAX / 2, isize::MAX - 1]).unwrap();
+        assert_eq!(p.value_at(4), Some(2 * (isize::MAX as i128 - 1)));
+        assert_eq!(Polynomial::fit(&[2, 2, 2]).unwrap().degree(), 0);
+        // Next value is 2 * isize::MAX, used to be truncated silently
+        assert_eq!(
+            crate::solve(&[vec![0, isize::MAX / 2, isize::MAX - 1]]),
+            Err((0, FitError::Overflow))
+        );
+        // Each history fits but the sum doesn't
+        assert_eq!(
+            crate::solve(&[vec![1, 1], vec![isize::MAX, isize::MAX]]),
+            Err((1, FitError::Overflow))
+        );
+    }
+
+    #[test]
+    fn not_polynomial() {
+        let history = [1, 2, 4, 8, 16];
+        assert_eq!(
+            Polynomial::fit(&history),
+            Err(FitError::NotPolynomial { len: 5 })
+        );
+        assert_eq!(
+            fit_with(&history, Fallback::Error),
+            Err(FitError::NotPolynomial { len: 5 })
+        );
+        assert_eq!(fit_with(&history, Fallback::Skip), Ok(None));
+        assert!(Polynomial::fit(&[7]).is_err());
+        // Used to panic once the differences ran out of values
+        assert_eq!(
+            crate::solve(&[vec![1, 3, 5], history.to_vec()]),
+            Err((1, FitError::NotPolynomial { len: 5 }))
+        );
+
+        // Best line through (0, 1) (1, 2) (2, 4) (3, 8) (4, 16) is 3.6x - 1
+        let fit = fit_with(&history, Fallback::LeastSquares(1))
+            .unwrap()
+            .unwrap();
+        assert!(!fit.is_exact());
+        assert_eq!(
+            fit.coefficients(),
+            Some(vec![Ratio::from(-1), Ratio::new(18, 5)])
+        );
+        assert_eq!(fit.forward(1), Some(17.into()));
+        assert_eq!(
+            fit_with(&history, Fallback::LeastSquares(4)),
+            Err(FitError::DegreeTooHigh { degree: 4, len: 5 })
+        );
+
+        let exact = fit_with(&[1, 4, 9, 16], Fallback::LeastSquares(1))
+            .unwrap()
+            .unwrap();
+        assert!(exact.is_exact());
+        assert_eq!(exact.forward(1), Some(25.into()));
+    }
+
+    #[test]
+    fn least_squares_overflow() {
+        // The normal equations of the higher degrees don't fit in an i128, used to panic
+        let history: Vec<isize> = (0..21).map(|x| 1 << x).collect();
+        for degree in 0..20 {
+            match LeastSquares::fit(&history, degree) {
+                Ok(fit) => assert_eq!(fit.coefficients.len(), degree + 1),
+                Err(e) => assert_eq!(e, FitError::Overflow, "degree {}", degree),
+            }
+        }
+        assert_eq!(LeastSquares::fit(&history, 19), Err(FitError::Overflow));
+        assert_eq!(Ratio::from(1).checked_div(0.into()), None);
+        assert_eq!(Ratio::from(i128::MIN + 1).checked_sub(2.into()), None);
+        assert_eq!(
+            Ratio::from(3).checked_sub(Ratio::new(1, 2)),
+            Some(Ratio::new(5, 2))
+        );
+    }
+
     #[test]
     fn part_1() {
         let input = load_input("example");
-        assert_eq!(crate::solve(&input).0, 114);
+        assert_eq!(crate::solve(&input).unwrap().0, 114);
     }
 
     #[test]
     fn part_2() {
         let input = load_input("example");
-        assert_eq!(crate::solve(&input).1, 2);
+        assert_eq!(crate::solve(&input).unwrap().1, 2);
     }
 }
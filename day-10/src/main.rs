@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
 use std::fs::File;
 use std::io::prelude::*;
@@ -6,19 +6,105 @@ use std::io::BufReader;
 
 fn main() {
     let (map, animal) = load_input("input");
-    let (p_1, p_2) = solve(map, animal);
-    println!("Solution for part 1: {}", p_1);
-    println!("Solution for part 2: {}", p_2);
+    let (map, pipe_loop) = match prepare_map(map, animal) {
+        Ok(prepared) => prepared,
+        Err(e) => {
+            println!("Can't find the loop: {}", e);
+            return;
+        }
+    };
+    match std::env::args().nth(1).as_deref() {
+        None => {
+            let (p_1, p_2) = solve(&map, &pipe_loop);
+            println!("Solution for part 1: {}", p_1);
+            println!("Solution for part 2: {}", p_2);
+        }
+        Some("render") => {
+            let fill = std::env::args()
+                .nth(2)
+                .and_then(|f| f.chars().next())
+                .unwrap_or('█');
+            print!("{}", render(&map, &pipe_loop.tile_set(), fill));
+        }
+        Some("svg") => {
+            let path = std::env::args().nth(2).unwrap_or("loop.svg".to_string());
+            std::fs::write(&path, render_svg(&map, &pipe_loop.tile_set()))
+                .expect("Can't write the svg");
+            println!("Saved to {}", path);
+        }
+        Some("inside") => {
+            let strategy = match std::env::args().nth(2).as_deref() {
+                None | Some("scanline") => InsideStrategy::Scanline,
+                Some("shoelace") => InsideStrategy::Shoelace,
+                Some("flood") => InsideStrategy::FloodFill,
+                Some(_) => {
+                    println!("Usage: inside [scanline|shoelace|flood]");
+                    return;
+                }
+            };
+            println!(
+                "Tiles inside using {:?}: {}",
+                strategy,
+                count_inside_with(&map, &pipe_loop, strategy)
+            );
+        }
+        Some("loop") => {
+            let (farthest, distance) = pipe_loop.farthest();
+            println!(
+                "Loop of {} tiles running {}, farthest tile {:?} is {} steps away",
+                pipe_loop.len(),
+                if pipe_loop.is_clockwise() {
+                    "clockwise"
+                } else {
+                    "counterclockwise"
+                },
+                // Without the border
+                (farthest.0 - 1, farthest.1 - 1),
+                distance
+            );
+            // Coordinates as shown in the input, starting at 0
+            let x = std::env::args()
+                .nth(2)
+                .and_then(|x| x.parse::<usize>().ok());
+            let y = std::env::args()
+                .nth(3)
+                .and_then(|y| y.parse::<usize>().ok());
+            if let (Some(x), Some(y)) = (x, y) {
+                match pipe_loop.distances((x + 1, y + 1)) {
+                    Some((forward, backward)) => println!(
+                        "({}, {}) is {} steps forward and {} steps backward from the start",
+                        x, y, forward, backward
+                    ),
+                    None => println!("({}, {}) isn't on the loop", x, y),
+                }
+            }
+        }
+        _ => println!("Unknown command"),
+    }
 }
 
-fn solve(mut map: Vec<Vec<Tile>>, animal: (usize, usize)) -> (usize, usize) {
-    replace_animal(&mut map, animal);
-    let loop_map = find_loop(&map, animal);
-    (loop_map.len() / 2, count_inside_loop(&map, loop_map))
+fn solve(map: &[Vec<Tile>], pipe_loop: &PipeLoop) -> (usize, usize) {
+    (
+        pipe_loop.farthest().1,
+        count_inside_loop(map, pipe_loop.tile_set()),
+    )
+}
+
+fn prepare_map(
+    mut map: Vec<Vec<Tile>>,
+    animal: (usize, usize),
+) -> Result<(Vec<Vec<Tile>>, PipeLoop), StartError> {
+    replace_animal(&mut map, animal)?;
+    let pipe_loop = find_loop(&map, animal);
+    Ok((map, pipe_loop))
 }
 
 fn count_inside_loop(map: &[Vec<Tile>], loop_tiles: HashSet<(usize, usize)>) -> usize {
-    let mut counter = 0;
+    find_inside(map, &loop_tiles).len()
+}
+
+fn find_inside(map: &[Vec<Tile>], loop_tiles: &HashSet<(usize, usize)>) -> HashSet<(usize, usize)> {
+    let mut inside_tiles = HashSet::new();
     for (y, row) in map.iter().enumerate() {
         let mut inside = false;
 
@@ -34,7 +120,7 @@ fn count_inside_loop(map: &[Vec<Tile>], loop_tiles: HashSet<(usize, usize)>) ->
                 └──┐  -->  └─┐  -->  └┐  --> │
                    │         │        │      │
                 This is the same for the ┌┘ bend. No other corners matter so you only need to keep track of the east facing corners
-                and flip the inside bool when going over the opposite corner. 
+                and flip the inside bool when going over the opposite corner.
                 */
                 match tile {
                     Tile::Vertical => inside = !inside,
@@ -54,6 +140,75 @@ fn count_inside_loop(map: &[Vec<Tile>], loop_tiles: HashSet<(usize, usize)>) ->
                     Tile::Ground | Tile::Animal => unreachable!(),
                 }
             } else if inside {
+                inside_tiles.insert((x, y));
+            }
+        }
+    }
+    inside_tiles
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InsideStrategy {
+    /// `count_inside_loop`
+    Scanline,
+    /// `count_inside_shoelace`
+    Shoelace,
+    /// `count_inside_flood_fill`
+    FloodFill,
+}
+
+fn count_inside_with(map: &[Vec<Tile>], pipe_loop: &PipeLoop, strategy: InsideStrategy) -> usize {
+    match strategy {
+        InsideStrategy::Scanline => count_inside_loop(map, pipe_loop.tile_set()),
+        InsideStrategy::Shoelace => count_inside_shoelace(pipe_loop),
+        InsideStrategy::FloodFill => count_inside_flood_fill(map, &pipe_loop.tiles),
+    }
+}
+
+fn count_inside_shoelace(pipe_loop: &PipeLoop) -> usize {
+    // Pick's theorem connects the area of the polygon going through the centre of every loop tile
+    // to the points on the boundary (the loop tiles) and the points inside:
+    // A = i + b / 2 - 1  =>  i = A - b / 2 + 1
+    (pipe_loop.double_area().unsigned_abs() + 2 - pipe_loop.len()) / 2
+}
+
+fn count_inside_flood_fill(map: &[Vec<Tile>], loop_path: &[(usize, usize)]) -> usize {
+    // Every tile becomes 2x2 so that the gaps between 2 pipes that aren't connected become real tiles
+    // that the fill can squeeze through.
+    let height = map.len() * 2;
+    let width = map[0].len() * 2;
+    let mut walls = vec![vec![false; width]; height];
+    for &(x, y) in loop_path {
+        walls[y * 2][x * 2] = true;
+        let (first, second) = map[y][x].get_connected(x, y);
+        for (nx, ny) in [first, second] {
+            walls[y + ny][x + nx] = true;
+        }
+    }
+
+    // The ground border means (0, 0) is always outside
+    let mut outside = vec![vec![false; width]; height];
+    let mut queue: Vec<(usize, usize)> = vec![(0, 0)];
+    outside[0][0] = true;
+    while let Some((x, y)) = queue.pop() {
+        let neighbours = [
+            (x.wrapping_sub(1), y),
+            (x + 1, y),
+            (x, y.wrapping_sub(1)),
+            (x, y + 1),
+        ];
+        for (nx, ny) in neighbours {
+            if nx < width && ny < height && !walls[ny][nx] && !outside[ny][nx] {
+                outside[ny][nx] = true;
+                queue.push((nx, ny));
+            }
+        }
+    }
+
+    let mut counter = 0;
+    for y in 0..map.len() {
+        for x in 0..map[0].len() {
+            if !walls[y * 2][x * 2] && !outside[y * 2][x * 2] {
                 counter += 1;
             }
         }
@@ -61,9 +216,86 @@ fn count_inside_loop(map: &[Vec<Tile>], loop_tiles: HashSet<(usize, usize)>) ->
     counter
 }
 
+/// Draws the loop with box drawing characters and `fill` on the inside, everything else is blank.
+/// Leaves out the ground border added by `load_input`.
+fn render(map: &[Vec<Tile>], loop_tiles: &HashSet<(usize, usize)>, fill: char) -> String {
+    let inside = find_inside(map, loop_tiles);
+    let mut out = String::new();
+    for (y, row) in map.iter().enumerate().skip(1).take(map.len() - 2) {
+        for (x, tile) in row.iter().enumerate().skip(1).take(row.len() - 2) {
+            if loop_tiles.contains(&(x, y)) {
+                out.push(tile.box_char());
+            } else if inside.contains(&(x, y)) {
+                out.push(fill);
+            } else {
+                out.push(' ');
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+const SVG_CELL: usize = 4;
+
+/// Same as `render` but as an SVG image that stays readable for big maps
+fn render_svg(map: &[Vec<Tile>], loop_tiles: &HashSet<(usize, usize)>) -> String {
+    let inside = find_inside(map, loop_tiles);
+    let width = (map[0].len() - 2) * SVG_CELL;
+    let height = (map.len() - 2) * SVG_CELL;
+    let half = SVG_CELL / 2;
+    // Shift by one tile to remove the border
+    let center = |x: usize, y: usize| ((x - 1) * SVG_CELL + half, (y - 1) * SVG_CELL + half);
+
+    let mut inside: Vec<&(usize, usize)> = inside.iter().collect();
+    inside.sort_unstable();
+    let mut fill = String::new();
+    for (x, y) in inside {
+        fill.push_str(&format!(
+            "M{} {}h{}v{}h-{}z",
+            (x - 1) * SVG_CELL,
+            (y - 1) * SVG_CELL,
+            SVG_CELL,
+            SVG_CELL,
+            SVG_CELL
+        ));
+    }
+
+    let mut pipes: Vec<&(usize, usize)> = loop_tiles.iter().collect();
+    pipes.sort_unstable();
+    let mut lines = String::new();
+    for &(x, y) in pipes {
+        let (cx, cy) = center(x, y);
+        let (first, second) = map[y][x].get_connected(x, y);
+        for (nx, ny) in [first, second] {
+            let (ncx, ncy) = center(nx, ny);
+            // Only half of the way, the neighbour draws the other half
+            lines.push_str(&format!(
+                "M{} {}L{} {}",
+                cx,
+                cy,
+                (cx + ncx) as f64 / 2.0,
+                (cy + ncy) as f64 / 2.0
+            ));
+        }
+    }
+
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n\
+         <rect width=\"{w}\" height=\"{h}\" fill=\"white\"/>\n\
+         <path d=\"{fill}\" fill=\"gold\"/>\n\
+         <path d=\"{lines}\" stroke=\"black\" stroke-width=\"1\" fill=\"none\"/>\n\
+         </svg>\n",
+        w = width,
+        h = height,
+        fill = fill,
+        lines = lines
+    )
+}
+
 // Simply follows the pipes and avoids going back. Once it arrives at the start the loop is found
-fn find_loop(map: &[Vec<Tile>], start: (usize, usize)) -> HashSet<(usize, usize)> {
-    let mut loop_points: Vec<(usize, usize)> = Vec::new();
+fn find_loop(map: &[Vec<Tile>], start: (usize, usize)) -> PipeLoop {
+    let mut loop_points: Vec<(usize, usize)> = vec![start];
 
     let mut cur_point = start;
     // (0, 0) can't be visited so it's fine as a placeholder for the start
@@ -82,57 +314,188 @@ fn find_loop(map: &[Vec<Tile>], start: (usize, usize)) -> HashSet<(usize, usize)
         last_visited = (cur_x, cur_y);
         cur_point = *loop_points.last().unwrap();
         if cur_point == start {
+            loop_points.pop();
             break;
         }
     }
-    HashSet::from_iter(loop_points)
-}
-
-/// Replaces the animal with the pipe piece
-fn replace_animal(map: &mut [Vec<Tile>], animal: (usize, usize)) {
-    let animal_x = animal.0;
-    let animal_y = animal.1;
-
-    let mut n = false;
-    let mut s = false;
-    let mut w = false;
-    let mut e = false;
-    // Check the 4 directions and see if they have a pipe that points to the animal
-    if matches!(
-        map[animal_y - 1][animal_x],
-        Tile::Vertical | Tile::SouthWest | Tile::SouthEast
-    ) {
-        n = true;
-    }
-    if matches!(
-        map[animal_y + 1][animal_x],
-        Tile::Vertical | Tile::NorthEast | Tile::NorthWest
-    ) {
-        s = true;
-    }
-    if matches!(
-        map[animal_y][animal_x - 1],
-        Tile::Horizontal | Tile::NorthEast | Tile::SouthEast
-    ) {
-        w = true;
-    }
-    if matches!(
-        map[animal_y][animal_x + 1],
-        Tile::Horizontal | Tile::SouthWest | Tile::NorthWest
-    ) {
-        e = true;
-    }
-
-    // Replace the animal with the pipe that points in the 2 directions that have connections
-    map[animal_y][animal_x] = match (n, s, w, e) {
-        (true, true, false, false) => Tile::Vertical,
-        (true, false, true, false) => Tile::NorthWest,
-        (true, false, false, true) => Tile::NorthEast,
-        (false, true, true, false) => Tile::SouthWest,
-        (false, true, false, true) => Tile::SouthEast,
-        (false, false, true, true) => Tile::Horizontal,
-        _ => unreachable!(),
-    };
+    PipeLoop::new(loop_points)
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum Direction {
+    North,
+    South,
+    West,
+    East,
+}
+
+impl Direction {
+    fn between(from: (usize, usize), to: (usize, usize)) -> Self {
+        match (
+            to.0 as isize - from.0 as isize,
+            to.1 as isize - from.1 as isize,
+        ) {
+            (0, -1) => Direction::North,
+            (0, 1) => Direction::South,
+            (-1, 0) => Direction::West,
+            (1, 0) => Direction::East,
+            _ => panic!("{:?} and {:?} aren't next to each other", from, to),
+        }
+    }
+}
+
+/// The loop in the order the pipes connect, starting at the animal
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PipeLoop {
+    tiles: Vec<(usize, usize)>,
+    /// `directions[i]` is the way from `tiles[i]` to the next tile
+    directions: Vec<Direction>,
+    positions: HashMap<(usize, usize), usize>,
+}
+
+impl PipeLoop {
+    fn new(tiles: Vec<(usize, usize)>) -> Self {
+        let directions = (0..tiles.len())
+            .map(|i| Direction::between(tiles[i], tiles[(i + 1) % tiles.len()]))
+            .collect();
+        let positions = tiles.iter().enumerate().map(|(i, t)| (*t, i)).collect();
+        Self {
+            tiles,
+            directions,
+            positions,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    fn tile_set(&self) -> HashSet<(usize, usize)> {
+        HashSet::from_iter(self.tiles.iter().copied())
+    }
+
+    /// Steps from the start to `tile` going forward and going backwards, `None` if not on the loop
+    fn distances(&self, tile: (usize, usize)) -> Option<(usize, usize)> {
+        let i = *self.positions.get(&tile)?;
+        Some((i, (self.len() - i) % self.len()))
+    }
+
+    /// The tile that takes the most steps to reach in either direction
+    fn farthest(&self) -> ((usize, usize), usize) {
+        let i = self.len() / 2;
+        (self.tiles[i], i)
+    }
+
+    /// Shoelace formula: 2A = sum(x_i * y_i+1 - x_i+1 * y_i)
+    /// Positive when going clockwise since y grows downwards.
+    fn double_area(&self) -> isize {
+        let mut double_area: isize = 0;
+        for (i, &(x, y)) in self.tiles.iter().enumerate() {
+            let (next_x, next_y) = self.tiles[(i + 1) % self.len()];
+            double_area += (x * next_y) as isize - (next_x * y) as isize;
+        }
+        double_area
+    }
+
+    fn is_clockwise(&self) -> bool {
+        self.double_area() > 0
+    }
+}
+
+const PIPES: [Tile; 6] = [
+    Tile::Vertical,
+    Tile::Horizontal,
+    Tile::NorthEast,
+    Tile::NorthWest,
+    Tile::SouthWest,
+    Tile::SouthEast,
+];
+
+#[derive(Debug, PartialEq, Eq)]
+enum StartError {
+    NoLoop {
+        tried: Vec<Tile>,
+    },
+    Ambiguous {
+        closing: Vec<Tile>,
+        tried: Vec<Tile>,
+    },
+}
+
+impl Display for StartError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let join = |tiles: &[Tile]| {
+            tiles
+                .iter()
+                .map(|t| t.to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        match self {
+            StartError::NoLoop { tried } => {
+                write!(f, "none of {} close the loop at the animal", join(tried))
+            }
+            StartError::Ambiguous { closing, tried } => write!(
+                f,
+                "tried {} and more than one closes the loop at the animal: {}",
+                join(tried),
+                join(closing)
+            ),
+        }
+    }
+}
+
+/// Replaces the animal with the only pipe piece that makes a loop through it
+fn replace_animal(map: &mut [Vec<Tile>], animal: (usize, usize)) -> Result<(), StartError> {
+    // Junk pipes next to the animal can point at it without being part of the loop
+    // so each shape has to be followed all the way around
+    let closing: Vec<Tile> = PIPES
+        .iter()
+        .filter(|shape| closes_loop(map, animal, **shape))
+        .copied()
+        .collect();
+    match closing[..] {
+        [shape] => {
+            map[animal.1][animal.0] = shape;
+            Ok(())
+        }
+        [] => Err(StartError::NoLoop {
+            tried: PIPES.to_vec(),
+        }),
+        _ => Err(StartError::Ambiguous {
+            closing,
+            tried: PIPES.to_vec(),
+        }),
+    }
+}
+
+/// Follows the pipes from `start` as if it was `shape` and checks if they lead back into the other end
+fn closes_loop(map: &[Vec<Tile>], start: (usize, usize), shape: Tile) -> bool {
+    let (first, second) = shape.get_connected(start.0, start.1);
+    let mut last_visited = start;
+    let mut cur_point = first;
+    // Can't be longer than the whole map
+    for _ in 0..map.len() * map[0].len() {
+        if cur_point == start {
+            return last_visited == second;
+        }
+        let tile = map[cur_point.1][cur_point.0];
+        if matches!(tile, Tile::Ground | Tile::Animal) {
+            return false;
+        }
+        let (a, b) = tile.get_connected(cur_point.0, cur_point.1);
+        let next = if a == last_visited {
+            b
+        } else if b == last_visited {
+            a
+        } else {
+            // Doesn't connect back to where we came from
+            return false;
+        };
+        last_visited = cur_point;
+        cur_point = next;
+    }
+    false
 }
 
 fn load_input(name: &str) -> (Vec<Vec<Tile>>, (usize, usize)) {
@@ -188,6 +551,21 @@ impl Tile {
     }
 }
 
+impl Tile {
+    fn box_char(self) -> char {
+        match self {
+            Tile::Vertical => '│',
+            Tile::Horizontal => '─',
+            Tile::NorthEast => '└',
+            Tile::NorthWest => '┘',
+            Tile::SouthWest => '┐',
+            Tile::SouthEast => '┌',
+            Tile::Ground => ' ',
+            Tile::Animal => 'S',
+        }
+    }
+}
+
 impl Display for Tile {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -227,21 +605,236 @@ impl From<char> for Tile {
 mod tests {
     use super::*;
 
+    fn parse(lines: &[&str]) -> (Vec<Vec<Tile>>, (usize, usize)) {
+        let width = lines[0].len() + 2;
+        let mut map = vec![vec![Tile::Ground; width]];
+        let mut animal = (0, 0);
+        for (y, line) in lines.iter().enumerate() {
+            let mut row = vec![Tile::Ground];
+            for (x, c) in line.chars().enumerate() {
+                if c == 'S' {
+                    animal = (x + 1, y + 1);
+                }
+                row.push(c.into());
+            }
+            row.push(Tile::Ground);
+            map.push(row);
+        }
+        map.push(vec![Tile::Ground; width]);
+        (map, animal)
+    }
+
+    #[test]
+    fn rendering() {
+        let (map, animal) = parse(&["7....", ".S-7.", ".|F|.", ".L-J.", "....."]);
+        let (map, pipe_loop) = prepare_map(map, animal).unwrap();
+        assert_eq!(
+            render(&map, &pipe_loop.tile_set(), '#'),
+            "     \n ┌─┐ \n │#│ \n └─┘ \n     \n"
+        );
+
+        let (map, animal) = parse(&["S--7", "|..|", "L--J"]);
+        let (map, pipe_loop) = prepare_map(map, animal).unwrap();
+        let svg = render_svg(&map, &pipe_loop.tile_set());
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+
+    /// Builds a random loop by drawing the outline of a random tree. Nodes of the tree sit on every other cell
+    /// and the edges fill the gaps between them, so the shape never has holes or corners that only touch diagonally.
+    /// Returns the map, a tile of the loop and how many tiles are inside.
+    fn random_loop(seed: &mut u64, size: usize) -> (Vec<Vec<Tile>>, (usize, usize), usize) {
+        let mut random = |max: usize| {
+            *seed ^= *seed << 13;
+            *seed ^= *seed >> 7;
+            *seed ^= *seed << 17;
+            (*seed % max as u64) as usize
+        };
+        let cells = size * 2 - 1;
+        let mut filled = vec![vec![false; cells]; cells];
+        let mut visited = vec![vec![false; size]; size];
+        let start = (random(size), random(size));
+        let mut stack = vec![start];
+        visited[start.1][start.0] = true;
+        filled[start.1 * 2][start.0 * 2] = true;
+        while let Some(&(x, y)) = stack.last() {
+            let options: Vec<(usize, usize)> = [
+                (x.wrapping_sub(1), y),
+                (x + 1, y),
+                (x, y.wrapping_sub(1)),
+                (x, y + 1),
+            ]
+            .into_iter()
+            .filter(|(nx, ny)| *nx < size && *ny < size && !visited[*ny][*nx])
+            .collect();
+            // Stop some branches early so the shapes aren't always full squares
+            if options.is_empty() || random(5) == 0 {
+                stack.pop();
+                continue;
+            }
+            let (nx, ny) = options[random(options.len())];
+            visited[ny][nx] = true;
+            filled[ny * 2][nx * 2] = true;
+            filled[y + ny][x + nx] = true;
+            stack.push((nx, ny));
+        }
+
+        // Blow every cell up so there is room for tiles inside the loop
+        let scale = 1 + random(3);
+        let filled: Vec<Vec<bool>> = (0..cells * scale)
+            .map(|y| {
+                (0..cells * scale)
+                    .map(|x| filled[y / scale][x / scale])
+                    .collect()
+            })
+            .collect();
+        let cells = cells * scale;
+
+        // Pipes run along the corners of the cells, with a ground border around them
+        let is_filled = |x: isize, y: isize| {
+            x >= 0
+                && y >= 0
+                && (x as usize) < cells
+                && (y as usize) < cells
+                && filled[y as usize][x as usize]
+        };
+        let mut map = vec![vec![Tile::Ground; cells + 3]; cells + 3];
+        let mut on_loop = (0, 0);
+        let mut inside = 0;
+        for y in 0..=cells as isize {
+            for x in 0..=cells as isize {
+                let around = [
+                    is_filled(x - 1, y - 1),
+                    is_filled(x, y - 1),
+                    is_filled(x - 1, y),
+                    is_filled(x, y),
+                ];
+                let n = around[0] != around[1];
+                let s = around[2] != around[3];
+                let w = around[0] != around[2];
+                let e = around[1] != around[3];
+                let tile = match (n, s, w, e) {
+                    (true, true, false, false) => Tile::Vertical,
+                    (true, false, true, false) => Tile::NorthWest,
+                    (true, false, false, true) => Tile::NorthEast,
+                    (false, true, true, false) => Tile::SouthWest,
+                    (false, true, false, true) => Tile::SouthEast,
+                    (false, false, true, true) => Tile::Horizontal,
+                    (false, false, false, false) => {
+                        if around.iter().all(|f| *f) {
+                            inside += 1;
+                        }
+                        // Junk pipes that aren't part of the loop
+                        [Tile::Ground, Tile::Vertical, Tile::SouthWest][random(3)]
+                    }
+                    _ => unreachable!(),
+                };
+                let pos = (x as usize + 1, y as usize + 1);
+                if !matches!((n, s, w, e), (false, false, false, false)) {
+                    on_loop = pos;
+                }
+                map[pos.1][pos.0] = tile;
+            }
+        }
+        (map, on_loop, inside)
+    }
+
+    #[test]
+    fn pipe_loop() {
+        let (map, animal) = parse(&["S--7", "|..|", "L--J"]);
+        let (_, pipe_loop) = prepare_map(map, animal).unwrap();
+        assert_eq!(pipe_loop.len(), 10);
+        assert_eq!(pipe_loop.tiles[0], (1, 1));
+        assert_eq!(pipe_loop.farthest(), ((4, 3), 5));
+        assert_eq!(pipe_loop.distances((1, 2)), Some((1, 9)));
+        assert_eq!(pipe_loop.distances((1, 1)), Some((0, 0)));
+        assert_eq!(pipe_loop.distances((2, 2)), None);
+        // S is a F pipe which goes south first
+        assert_eq!(pipe_loop.directions[0], Direction::South);
+        assert!(!pipe_loop.is_clockwise());
+        let reversed = PipeLoop::new(pipe_loop.tiles.iter().rev().copied().collect());
+        assert!(reversed.is_clockwise());
+    }
+
+    #[test]
+    fn start_inference() {
+        // The 7 to the left of S points at it but is junk, the old check saw 3 connections
+        let (map, animal) = parse(&[".....", "7S-7.", ".|.|.", ".L-J."]);
+        let (map, _) = prepare_map(map, animal).unwrap();
+        assert_eq!(map[2][2], Tile::SouthEast);
+
+        let (map, animal) = parse(&["..", "S-", ".."]);
+        assert_eq!(
+            prepare_map(map, animal).unwrap_err(),
+            StartError::NoLoop {
+                tried: PIPES.to_vec()
+            }
+        );
+
+        // Two loops meet at S
+        let (map, animal) = parse(&[".F7..", ".||..", ".LS7.", "..LJ.", "....."]);
+        let e = prepare_map(map, animal).unwrap_err();
+        assert_eq!(
+            e,
+            StartError::Ambiguous {
+                closing: vec![Tile::NorthWest, Tile::SouthEast],
+                tried: PIPES.to_vec()
+            }
+        );
+        assert_eq!(
+            e.to_string(),
+            "tried | - L J 7 F and more than one closes the loop at the animal: J F"
+        );
+    }
+
+    #[test]
+    fn strategies_agree() {
+        let mut seed = 0x2023_1010;
+        for i in 0..200 {
+            let (map, start, inside) = random_loop(&mut seed, 2 + i % 9);
+            let pipe_loop = find_loop(&map, start);
+            for strategy in [
+                InsideStrategy::Scanline,
+                InsideStrategy::Shoelace,
+                InsideStrategy::FloodFill,
+            ] {
+                assert_eq!(
+                    count_inside_with(&map, &pipe_loop, strategy),
+                    inside,
+                    "{:?} on loop {}",
+                    strategy,
+                    i
+                );
+            }
+        }
+    }
+
     #[test]
     fn part_1() {
         let (map, animal) = load_input("example");
-        assert_eq!(crate::solve(map, animal).0, 8);
+        let (map, pipe_loop) = prepare_map(map, animal).unwrap();
+        assert_eq!(crate::solve(&map, &pipe_loop).0, 8);
     }
 
     #[test]
     fn part_2() {
         let (map, animal) = load_input("example2");
-        assert_eq!(crate::solve(map, animal).1, 8);
+        let (map, pipe_loop) = prepare_map(map, animal).unwrap();
+        assert_eq!(
+            count_inside_with(&map, &pipe_loop, InsideStrategy::Shoelace),
+            8
+        );
+        assert_eq!(
+            count_inside_with(&map, &pipe_loop, InsideStrategy::FloodFill),
+            8
+        );
+        assert_eq!(crate::solve(&map, &pipe_loop).1, 8);
     }
 
     #[test]
     fn part_2_2() {
         let (map, animal) = load_input("example3");
-        assert_eq!(crate::solve(map, animal).1, 10);
+        let (map, pipe_loop) = prepare_map(map, animal).unwrap();
+        assert_eq!(crate::solve(&map, &pipe_loop).1, 10);
     }
 }
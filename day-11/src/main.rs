@@ -1,135 +1,292 @@
-use std::collections::HashSet;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
 
-type Pair = ((usize, usize), (usize, usize));
-
 fn main() {
-    let (galaxies, empty_rows, empty_cols) = load_input("input");
-    let solutions = solve(&galaxies, &empty_rows, &empty_cols);
-    println!("Solution for part 1: {}", solutions.0);
-    println!("Solution for part 2: {}", solutions.1);
-}
+    let universe = load_input("input");
+    let factors: Result<Vec<u128>, String> = std::env::args()
+        .skip(1)
+        .map(|f| match f.parse() {
+            Ok(factor) if factor >= 1 => Ok(factor),
+            _ => Err(f),
+        })
+        .collect();
+    let factors = match factors {
+        Ok(factors) => factors,
+        Err(f) => {
+            println!("Expansion factor {} has to be a number of at least 1", f);
+            return;
+        }
+    };
+    if factors.is_empty() {
+        let solutions = solve(&universe);
+        println!("Solution for part 1: {}", solutions.0);
+        println!("Solution for part 2: {}", solutions.1);
+        return;
+    }
 
-fn solve(
-    galaxies: &[(usize, usize)],
-    empty_rows: &HashSet<usize>,
-    empty_cols: &HashSet<usize>,
-) -> (usize, usize) {
-    let mut result_1 = 0;
-    let mut result_2 = 0;
-    for pair in create_pairs(galaxies) {
-        let distance = get_distance(pair, empty_rows, empty_cols);
-        result_1 += distance.0;
-        result_2 += distance.1;
+    for (factor, total) in factors.iter().zip(universe.totals(&factors)) {
+        println!("Expansion factor {}: total {}", factor, total);
+        if let Some(((a, b), distance)) = universe.closest_pair(*factor) {
+            println!(
+                "    closest pair {:?} {:?} at {}",
+                universe.galaxies[a], universe.galaxies[b], distance
+            );
+        }
+        if let Some(((a, b), distance)) = universe.farthest_pair(*factor) {
+            println!(
+                "    farthest pair {:?} {:?} at {}",
+                universe.galaxies[a], universe.galaxies[b], distance
+            );
+        }
     }
-    (result_1, result_2)
 }
 
-fn get_distance(
-    pair: Pair,
-    empty_rows: &HashSet<usize>,
-    empty_cols: &HashSet<usize>,
-) -> (usize, usize) {
-    // This is just to make the rest more readable
-    let first = pair.0;
-    let second = pair.1;
-    let min_x = first.0.min(second.0);
-    let max_x = first.0.max(second.0);
-    let min_y = first.1.min(second.1);
-    let max_y = first.1.max(second.1);
-
-    let mut distance_normal = 0;
-    let mut distance_expand = 0;
+fn solve(universe: &Universe) -> (u128, u128) {
+    let totals = universe.totals(&[2, 1_000_000]);
+    (totals[0], totals[1])
+}
+
+/// Galaxies are indexed by their position in `galaxies`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Universe {
+    galaxies: Vec<(usize, usize)>,
+    /// Sorted
+    empty_rows: Vec<usize>,
+    /// Sorted
+    empty_cols: Vec<usize>,
+}
+
+impl Universe {
+    fn from_map(lines: impl Iterator<Item = String>) -> Self {
+        let mut lines = lines.peekable();
+
+        let mut empty_rows = Vec::new();
+        // Peekable iterator is used to create a vec of bools for every column
+        let mut empty_column_check = vec![false; lines.peek().map_or(0, |l| l.len())];
+        let mut galaxies: Vec<(usize, usize)> = Vec::new();
+
+        for (y, line) in lines.enumerate() {
+            let mut galaxy_in_row_found = false;
+            for (x, c) in line.chars().enumerate() {
+                if let '#' = c {
+                    galaxy_in_row_found = true;
+                    empty_column_check[x] = true;
+                    galaxies.push((x, y));
+                }
+            }
+            if !galaxy_in_row_found {
+                empty_rows.push(y);
+            }
+        }
+        let empty_cols: Vec<usize> = empty_column_check
+            .iter()
+            .enumerate()
+            .filter_map(|(x, galaxy)| if !*galaxy { Some(x) } else { None })
+            .collect();
+        Self {
+            galaxies,
+            empty_rows,
+            empty_cols,
+        }
+    }
+
+    /// Where the galaxy ends up when every empty row and column is replaced by `factor` of them
+    fn expanded(&self, galaxy: usize, factor: u128) -> (u128, u128) {
+        let (x, y) = self.galaxies[galaxy];
+        (
+            expand(x, &self.empty_cols, factor),
+            expand(y, &self.empty_rows, factor),
+        )
+    }
+
+    fn distance(&self, a: usize, b: usize, factor: u128) -> u128 {
+        let (a_x, a_y) = self.expanded(a, factor);
+        let (b_x, b_y) = self.expanded(b, factor);
+        a_x.abs_diff(b_x) + a_y.abs_diff(b_y)
+    }
+
     /*
-    The shortest distance between 2 points on a grid is the Manhattan distance but because of the expanding
-    each "step" can be much bigger. To deal with that you simply walk the path and if you get to a row/column
-    that is expanded you add the expand value instead of just 1.
-    Walking the path is just going from min(x) + 1 to max(x) and then from min(y) + 1 to max(y). The + 1 is to
-    skip the tile you start on.
+    The Manhattan distance can be split into the x and y parts which can be summed up separately.
+    Once the coordinates of an axis are sorted the i-th one is bigger than the i ones before it, so it
+    adds c_i * i - (c_0 + ... + c_i-1) to the sum of all the distances.
+    Every empty line between 2 galaxies adds (factor - 1) to their distance. The amount of empty lines before
+    a coordinate grows with it, so the same trick works on them. That splits the total into
+    plain + crossed * (factor - 1), which only needs a single pass for any amount of factors.
     */
-    for x in (min_x + 1)..=max_x {
-        if empty_cols.contains(&x) {
-            distance_expand += 1;
-        } else {
-            distance_normal += 1;
+    fn totals(&self, factors: &[u128]) -> Vec<u128> {
+        let xs: Vec<usize> = self.galaxies.iter().map(|g| g.0).collect();
+        let ys: Vec<usize> = self.galaxies.iter().map(|g| g.1).collect();
+        let (plain_x, crossed_x) = axis_sums(xs, &self.empty_cols);
+        let (plain_y, crossed_y) = axis_sums(ys, &self.empty_rows);
+        let plain = plain_x + plain_y;
+        let crossed = crossed_x + crossed_y;
+        factors.iter().map(|f| plain + crossed * (f - 1)).collect()
+    }
+
+    /// Indexes of the closest pair and their distance, `None` with less than 2 galaxies
+    fn closest_pair(&self, factor: u128) -> Option<((usize, usize), u128)> {
+        let mut expanded: Vec<((u128, u128), usize)> = (0..self.galaxies.len())
+            .map(|i| (self.expanded(i, factor), i))
+            .collect();
+        expanded.sort_unstable();
+
+        // Sweep from left to right, only galaxies closer on the x axis than the best pair can beat it
+        let mut best: Option<((usize, usize), u128)> = None;
+        for (i, &((x, y), a)) in expanded.iter().enumerate() {
+            for &((other_x, other_y), b) in &expanded[i + 1..] {
+                let dx = other_x - x;
+                if best.is_some_and(|(_, d)| dx >= d) {
+                    break;
+                }
+                let distance = dx + y.abs_diff(other_y);
+                if best.is_none_or(|(_, d)| distance < d) {
+                    best = Some(((a.min(b), a.max(b)), distance));
+                }
+            }
         }
+        best
     }
-    for y in (min_y + 1)..=max_y {
-        if empty_rows.contains(&y) {
-            distance_expand += 1;
-        } else {
-            distance_normal += 1;
+
+    /// Indexes of the farthest pair and their distance, `None` with less than 2 galaxies
+    fn farthest_pair(&self, factor: u128) -> Option<((usize, usize), u128)> {
+        if self.galaxies.len() < 2 {
+            return None;
         }
+        // |x1 - x2| + |y1 - y2| is the biggest of (x + y) and (x - y) differences,
+        // so only the galaxies with the smallest and biggest of those matter
+        let rotated: Vec<(i128, i128)> = (0..self.galaxies.len())
+            .map(|i| {
+                let (x, y) = self.expanded(i, factor);
+                (x as i128 + y as i128, x as i128 - y as i128)
+            })
+            .collect();
+        let by_sum = |i: &usize| rotated[*i].0;
+        let by_diff = |i: &usize| rotated[*i].1;
+        let indexes = 0..self.galaxies.len();
+        let candidates = [
+            (
+                indexes.clone().min_by_key(by_sum).unwrap(),
+                indexes.clone().max_by_key(by_sum).unwrap(),
+            ),
+            (
+                indexes.clone().min_by_key(by_diff).unwrap(),
+                indexes.max_by_key(by_diff).unwrap(),
+            ),
+        ];
+        candidates
+            .iter()
+            .map(|&(a, b)| ((a.min(b), a.max(b)), self.distance(a, b, factor)))
+            .max_by_key(|(_, d)| *d)
     }
-    (
-        distance_normal + distance_expand * 2,
-        distance_normal + distance_expand * 1_000_000,
-    )
 }
 
-/// Creates unique pairs for all galaxies given
-fn create_pairs(galaxies: &[(usize, usize)]) -> HashSet<Pair> {
-    let mut pairs: HashSet<Pair> = HashSet::new();
-    for first in galaxies {
-        for second in galaxies {
-            if first == second {
-                continue;
-            }
-            // Sorted slice is used to turn (B, A) into (A, B)
-            let mut points = [first, second];
-            points.sort();
-            pairs.insert((*points[0], *points[1]));
-        }
+/// Where `coord` ends up when every empty line before it becomes `factor` lines, `factor` has to be at least 1
+fn expand(coord: usize, empty: &[usize], factor: u128) -> u128 {
+    coord as u128 + empty.partition_point(|e| *e < coord) as u128 * (factor - 1)
+}
+
+/// Sum of all distances between the coordinates and sum of all the empty lines between them
+fn axis_sums(mut coords: Vec<usize>, empty: &[usize]) -> (u128, u128) {
+    coords.sort_unstable();
+    let mut plain = 0;
+    let mut crossed = 0;
+    let mut plain_prefix = 0;
+    let mut crossed_prefix = 0;
+    for (i, c) in coords.iter().enumerate() {
+        let c = *c as u128;
+        let empty_before = empty.partition_point(|e| (*e as u128) < c) as u128;
+        plain += c * i as u128 - plain_prefix;
+        crossed += empty_before * i as u128 - crossed_prefix;
+        plain_prefix += c;
+        crossed_prefix += empty_before;
     }
-    pairs
+    (plain, crossed)
 }
 
-fn load_input(name: &str) -> (Vec<(usize, usize)>, HashSet<usize>, HashSet<usize>) {
+fn load_input(name: &str) -> Universe {
     let file = File::open(name).unwrap_or_else(|_| panic!("No \"{}\" file found", name));
     let reader = BufReader::new(file);
-    let mut lines = reader.lines().peekable();
-
-    let mut empty_rows = HashSet::new();
-    // Peekable iterator is used to create a vec of bools for every column
-    let mut empty_column_check = vec![false; lines.peek().unwrap().as_ref().unwrap().len()];
-    let mut galaxies: Vec<(usize, usize)> = Vec::new();
-
-    for (y, line) in lines.map(|l| l.unwrap()).enumerate() {
-        let mut galaxy_in_row_found = false;
-        for (x, c) in line.chars().enumerate() {
-            if let '#' = c {
-                galaxy_in_row_found = true;
-                empty_column_check[x] = true;
-                galaxies.push((x, y));
-            }
-        }
-        if !galaxy_in_row_found {
-            empty_rows.insert(y);
-        }
-    }
-    let empty_columns: HashSet<usize> = empty_column_check
-        .iter()
-        .enumerate()
-        .filter_map(|(x, galaxy)| if !*galaxy { Some(x) } else { None })
-        .collect();
-    (galaxies, empty_rows, empty_columns)
+    Universe::from_map(reader.lines().map(|l| l.unwrap()))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// Walks the path between every pair like the original solution did, without using `expand`
+    fn brute_force(universe: &Universe, factor: u128) -> (u128, Vec<u128>) {
+        let walk = |from: usize, to: usize, empty: &[usize]| -> u128 {
+            (from.min(to) + 1..=from.max(to))
+                .map(|i| if empty.contains(&i) { factor } else { 1 })
+                .sum()
+        };
+        let mut distances = Vec::new();
+        for (i, a) in universe.galaxies.iter().enumerate() {
+            for b in &universe.galaxies[i + 1..] {
+                distances.push(
+                    walk(a.0, b.0, &universe.empty_cols) + walk(a.1, b.1, &universe.empty_rows),
+                );
+            }
+        }
+        (distances.iter().sum(), distances)
+    }
+
+    #[test]
+    fn universe() {
+        let mut seed: u64 = 0x2023_1111;
+        let mut random = |max: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % max) as usize
+        };
+        for _ in 0..20 {
+            let mut map = vec![vec!['.'; 60]; 60];
+            for _ in 0..100 {
+                map[random(60)][random(60)] = '#';
+            }
+            let universe = Universe::from_map(map.iter().map(|row| row.iter().collect()));
+            let factors = [1, 2, 10, 1_000_000];
+            let totals = universe.totals(&factors);
+            for (factor, total) in factors.iter().zip(totals) {
+                let (brute_total, distances) = brute_force(&universe, *factor);
+                assert_eq!(total, brute_total);
+                let (pair, closest) = universe.closest_pair(*factor).unwrap();
+                assert_eq!(closest, *distances.iter().min().unwrap());
+                assert_eq!(universe.distance(pair.0, pair.1, *factor), closest);
+                let (pair, farthest) = universe.farthest_pair(*factor).unwrap();
+                assert_eq!(farthest, *distances.iter().max().unwrap());
+                assert_eq!(universe.distance(pair.0, pair.1, *factor), farthest);
+            }
+        }
+
+        // Every distance on a line of n galaxies: sum of d * (n - d) = n * (n^2 - 1) / 6
+        let n = 100_000;
+        let universe = Universe {
+            galaxies: (0..n).map(|x| (x, 0)).collect(),
+            empty_rows: Vec::new(),
+            empty_cols: Vec::new(),
+        };
+        let n = n as u128;
+        assert_eq!(universe.totals(&[2]), vec![n * (n * n - 1) / 6]);
+        assert_eq!(universe.closest_pair(2), Some(((0, 1), 1)));
+        assert_eq!(
+            universe.farthest_pair(2),
+            Some(((0, n as usize - 1), n - 1))
+        );
+    }
+
     #[test]
     fn part_1() {
-        let (galaxies, empty_rows, empty_cols) = load_input("example");
-        assert_eq!(solve(&galaxies, &empty_rows, &empty_cols).0, 374);
+        let universe = load_input("example");
+        assert_eq!(solve(&universe).0, 374);
     }
 
     #[test]
     fn part_2() {
-        let (galaxies, empty_rows, empty_cols) = load_input("example");
-        assert_eq!(solve(&galaxies, &empty_rows, &empty_cols).1, 82000210);
+        let universe = load_input("example");
+        assert_eq!(solve(&universe).1, 82000210);
+        assert_eq!(universe.totals(&[10, 100]), vec![1030, 8410]);
     }
 }
@@ -8,9 +8,165 @@ type Input = Vec<(Vec<char>, Vec<usize>)>;
 type Cache = HashMap<(usize, usize, usize, Option<char>), usize>;
 
 fn main() {
+    if let Some("nonogram") = std::env::args().nth(1).as_deref() {
+        let name = std::env::args()
+            .nth(2)
+            .expect("Usage: nonogram <puzzle file>");
+        let nonogram = match load_nonogram(&name) {
+            Ok(nonogram) => nonogram,
+            Err(e) => {
+                println!("Can't read the nonogram: {}", e);
+                return;
+            }
+        };
+        let (solution, unique) = nonogram.solve();
+        match solution {
+            Some(grid) => {
+                for row in grid {
+                    println!("{}", row.iter().collect::<String>());
+                }
+                if unique {
+                    println!("The solution is unique");
+                } else {
+                    println!("There is more than one solution");
+                }
+            }
+            None => println!("No solution"),
+        }
+        return;
+    }
     let input = load_input("input");
+    if let Some("arrangements") = std::env::args().nth(1).as_deref() {
+        let line = match std::env::args()
+            .nth(2)
+            .and_then(|n| n.parse::<usize>().ok())
+            .filter(|line| (1..=input.len()).contains(line))
+        {
+            Some(line) => line,
+            None => {
+                println!(
+                    "Usage: arrangements <line from 1 to {}> [sample <amount>]",
+                    input.len()
+                );
+                return;
+            }
+        };
+        let (s, g) = &input[line - 1];
+        if let Some("sample") = std::env::args().nth(3).as_deref() {
+            let amount = std::env::args()
+                .nth(4)
+                .and_then(|n| n.parse::<usize>().ok())
+                .unwrap_or(1);
+            let seed = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos() as u64;
+            let mut rng = Rng::new(seed);
+            for _ in 0..amount {
+                match sample_arrangement(s, g, &mut rng) {
+                    Some(a) => println!("{}", a),
+                    None => println!("No valid arrangements"),
+                }
+            }
+        } else {
+            for a in Arrangements::new(s, g) {
+                println!("{}", a);
+            }
+        }
+        return;
+    }
+    if let Some("unfold") = std::env::args().nth(1).as_deref() {
+        let factor = std::env::args()
+            .nth(2)
+            .and_then(|n| n.parse::<usize>().ok())
+            .expect("Usage: unfold <factor>");
+        println!(
+            "Arrangements unfolded {} times: {}",
+            factor,
+            count_unfolded(&input, factor)
+        );
+        return;
+    }
     println!("Solution for part 1: {}", part_1(&input));
     println!("Solution for part 2: {}", part_2(&input));
+    println!(
+        "Solution for part 2 using the table: {}",
+        count_unfolded(&input, 5)
+    );
+}
+
+/// Sum of the arrangements of every row unfolded `factor` times, with the rows split between all threads
+fn count_unfolded(input: &Input, factor: usize) -> u128 {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = input.len().div_ceil(threads).max(1);
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = input
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|(s, g)| {
+                            let (row, groups) = unfold(s, g, factor);
+                            count_arrangements(&row, &groups)
+                        })
+                        .sum::<u128>()
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).sum()
+    })
+}
+
+/// Repeats the row `factor` times with a ? between and the groups `factor` times
+fn unfold(row: &[char], groups: &[usize], factor: usize) -> (Vec<char>, Vec<usize>) {
+    let mut new_row = Vec::with_capacity((row.len() + 1) * factor);
+    for i in 0..factor {
+        if i != 0 {
+            new_row.push('?');
+        }
+        new_row.extend_from_slice(row);
+    }
+    (new_row, groups.repeat(factor))
+}
+
+/*
+Bottom up version of `solve`. table[i][g] is the amount of arrangements for the row starting at i
+using the groups starting at g, so the answer ends up in table[0][0].
+Going from the back every position can either be a . which leaves everything for the next position,
+or the start of group g which needs group_len cells without a . followed by something that isn't #.
+The group then continues from after that separator.
+*/
+fn count_arrangements(row: &[char], groups: &[usize]) -> u128 {
+    let len = row.len();
+    // How many cells starting at i aren't a .
+    let mut open_run = vec![0; len + 1];
+    for i in (0..len).rev() {
+        if row[i] != '.' {
+            open_run[i] = open_run[i + 1] + 1;
+        }
+    }
+
+    let mut table = vec![vec![0u128; groups.len() + 1]; len + 2];
+    // Past the end of the row only running out of groups is valid
+    table[len][groups.len()] = 1;
+    table[len + 1][groups.len()] = 1;
+    for i in (0..len).rev() {
+        for g in 0..=groups.len() {
+            let mut count = 0;
+            if row[i] != '#' {
+                count += table[i + 1][g];
+            }
+            if g < groups.len() && row[i] != '.' {
+                let end = i + groups[g];
+                if open_run[i] >= groups[g] && (end == len || row[end] != '#') {
+                    count += table[end + 1][g + 1];
+                }
+            }
+            table[i][g] = count;
+        }
+    }
+    table[0][0]
 }
 
 fn part_1(input: &Input) -> usize {
@@ -127,6 +283,309 @@ fn solve_wrapped(
     }
 }
 
+/// Every valid arrangement of a row in lexicographic order (`#` comes before `.`).
+/// The counts from `solve` tell how many arrangements are behind each choice so the n-th one can be built directly.
+struct Arrangements {
+    symbols: Vec<char>,
+    groups: Vec<usize>,
+    cache: Cache,
+    next: usize,
+    total: usize,
+}
+
+impl Arrangements {
+    fn new(row: &[char], groups: &[usize]) -> Self {
+        let mut symbols = row.to_vec();
+        // Same trailing dot as in `part_1`
+        symbols.push('.');
+        let mut cache = HashMap::new();
+        let total = solve(&symbols, groups, 0, 0, 0, None, &mut cache);
+        Self {
+            symbols,
+            groups: groups.to_vec(),
+            cache,
+            next: 0,
+            total,
+        }
+    }
+
+    /// The arrangement at position `n` of the lexicographic order
+    fn nth_arrangement(&mut self, n: usize) -> String {
+        let mut n = n;
+        walk_arrangement(&self.symbols, &self.groups, &mut self.cache, |broken, _| {
+            if n < broken {
+                '#'
+            } else {
+                n -= broken;
+                '.'
+            }
+        })
+    }
+}
+
+impl Iterator for Arrangements {
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next == self.total {
+            return None;
+        }
+        self.next += 1;
+        Some(self.nth_arrangement(self.next - 1))
+    }
+}
+
+/// Picks one of the valid arrangements with every one of them being equally likely
+fn sample_arrangement(row: &[char], groups: &[usize], rng: &mut Rng) -> Option<String> {
+    let mut symbols = row.to_vec();
+    symbols.push('.');
+    let mut cache = HashMap::new();
+    if solve(&symbols, groups, 0, 0, 0, None, &mut cache) == 0 {
+        return None;
+    }
+    // Each side of a ? is picked based on how many arrangements it leads to
+    Some(walk_arrangement(
+        &symbols,
+        groups,
+        &mut cache,
+        |broken, working| {
+            if rng.below(broken + working) < broken {
+                '#'
+            } else {
+                '.'
+            }
+        },
+    ))
+}
+
+/// Goes through the row and lets `choose` pick what every ? becomes. `choose` gets the amount of
+/// arrangements left if the ? becomes a # and if it becomes a . and is only called when there is a choice.
+/// The row has to have at least one valid arrangement.
+fn walk_arrangement(
+    symbols: &[char],
+    groups: &[usize],
+    cache: &mut Cache,
+    mut choose: impl FnMut(usize, usize) -> char,
+) -> String {
+    let mut arrangement = String::new();
+    let mut gi = 0;
+    let mut broken_len = 0;
+    // The last symbol is the trailing dot
+    for i in 0..symbols.len() - 1 {
+        let symbol = if symbols[i] == '?' {
+            let broken = solve(symbols, groups, i, gi, broken_len, Some('#'), cache);
+            let working = solve(symbols, groups, i, gi, broken_len, Some('.'), cache);
+            match (broken, working) {
+                (_, 0) => '#',
+                (0, _) => '.',
+                _ => choose(broken, working),
+            }
+        } else {
+            symbols[i]
+        };
+        // Same rules as in `solve_wrapped`
+        if symbol == '#' {
+            broken_len += 1;
+        } else if broken_len != 0 {
+            gi += 1;
+            broken_len = 0;
+        }
+        arrangement.push(symbol);
+    }
+    arrangement
+}
+
+/// xorshift, good enough for picking arrangements
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // xorshift gets stuck on 0
+        Self(seed.max(1))
+    }
+
+    /// Random number in 0..max
+    fn below(&mut self, max: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % max as u64) as usize
+    }
+}
+
+type Grid = Vec<Vec<char>>;
+
+/// A 2D puzzle where every row and column is a day 12 row: `#` is a filled cell, `.` an empty one
+struct Nonogram {
+    rows: Vec<Vec<usize>>,
+    columns: Vec<Vec<usize>>,
+    /// Starting grid, `?` for unknown cells
+    grid: Grid,
+}
+
+impl Nonogram {
+    /// Returns a solution and if it's the only one
+    fn solve(&self) -> (Option<Grid>, bool) {
+        let mut solutions = Vec::new();
+        self.search(self.grid.clone(), &mut solutions);
+        let unique = solutions.len() == 1;
+        (solutions.into_iter().next(), unique)
+    }
+
+    /// Stops after finding 2 solutions since that is enough to know it's not unique
+    fn search(&self, mut grid: Grid, solutions: &mut Vec<Grid>) {
+        if solutions.len() >= 2 || !self.propagate(&mut grid) {
+            return;
+        }
+        let unknown = grid
+            .iter()
+            .enumerate()
+            .find_map(|(y, row)| row.iter().position(|c| *c == '?').map(|x| (x, y)));
+        match unknown {
+            None => solutions.push(grid),
+            Some((x, y)) => {
+                // Propagation got stuck so guess and backtrack
+                for guess in ['#', '.'] {
+                    let mut next = grid.clone();
+                    next[y][x] = guess;
+                    self.search(next, solutions);
+                }
+            }
+        }
+    }
+
+    /// Fills in every cell that only has one option in its row or column until nothing changes.
+    /// Returns `false` if the grid can't be solved.
+    fn propagate(&self, grid: &mut Grid) -> bool {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (y, groups) in self.rows.iter().enumerate() {
+                match solve_line(&grid[y], groups) {
+                    Some(line) => {
+                        if line != grid[y] {
+                            grid[y] = line;
+                            changed = true;
+                        }
+                    }
+                    None => return false,
+                }
+            }
+            for (x, groups) in self.columns.iter().enumerate() {
+                let column: Vec<char> = grid.iter().map(|row| row[x]).collect();
+                match solve_line(&column, groups) {
+                    Some(line) => {
+                        if line != column {
+                            for (row, c) in grid.iter_mut().zip(line) {
+                                row[x] = c;
+                            }
+                            changed = true;
+                        }
+                    }
+                    None => return false,
+                }
+            }
+        }
+        true
+    }
+}
+
+/// Fixes every ? that is the same in all valid arrangements of the line, `None` if there are none
+fn solve_line(line: &[char], groups: &[usize]) -> Option<Vec<char>> {
+    let count = |symbols: &[char]| {
+        let mut symbols = symbols.to_vec();
+        symbols.push('.');
+        solve(&symbols, groups, 0, 0, 0, None, &mut HashMap::new())
+    };
+    if count(line) == 0 {
+        return None;
+    }
+    let mut solved = line.to_vec();
+    let mut attempt = line.to_vec();
+    for i in 0..line.len() {
+        if line[i] != '?' {
+            continue;
+        }
+        attempt[i] = '#';
+        let broken = count(&attempt);
+        attempt[i] = '.';
+        let working = count(&attempt);
+        attempt[i] = '?';
+        if broken == 0 {
+            solved[i] = '.';
+        } else if working == 0 {
+            solved[i] = '#';
+        }
+    }
+    Some(solved)
+}
+
+/// Row clues, an empty line, column clues and optionally another empty line with the starting grid.
+/// Clues are written like in the day 12 input with 0 for an empty line.
+fn load_nonogram(name: &str) -> Result<Nonogram, String> {
+    let file = File::open(name).map_err(|_| format!("No \"{}\" file found", name))?;
+    let reader = BufReader::new(file);
+    let lines: Vec<String> = reader.lines().map(|l| l.unwrap()).collect();
+    parse_nonogram(&lines)
+}
+
+fn parse_nonogram(lines: &[String]) -> Result<Nonogram, String> {
+    let mut sections = lines.split(|l| l.trim().is_empty());
+    let parse_clues = |section: &[String]| -> Result<Vec<Vec<usize>>, String> {
+        section
+            .iter()
+            .map(|l| {
+                l.split(',')
+                    .map(|n| {
+                        n.trim()
+                            .parse::<usize>()
+                            .map_err(|_| format!("Clue \"{}\" isn't a number", n.trim()))
+                    })
+                    .filter(|n| *n != Ok(0))
+                    .collect()
+            })
+            .collect()
+    };
+    let rows = parse_clues(sections.next().ok_or("Missing row clues")?)?;
+    let columns = parse_clues(sections.next().ok_or("Missing column clues")?)?;
+    let grid: Grid = match sections.next() {
+        Some(section) if !section.is_empty() => {
+            section.iter().map(|l| l.chars().collect()).collect()
+        }
+        _ => vec![vec!['?'; columns.len()]; rows.len()],
+    };
+    // The solver indexes the grid with the clues so a wrong size would panic somewhere deep inside
+    if grid.len() != rows.len() {
+        return Err(format!(
+            "Starting grid has {} rows but there are {} row clues",
+            grid.len(),
+            rows.len()
+        ));
+    }
+    for (y, row) in grid.iter().enumerate() {
+        if row.len() != columns.len() {
+            return Err(format!(
+                "Row {} of the starting grid has {} cells but there are {} column clues",
+                y + 1,
+                row.len(),
+                columns.len()
+            ));
+        }
+        if let Some(c) = row.iter().find(|c| !matches!(c, '#' | '.' | '?')) {
+            return Err(format!(
+                "Row {} of the starting grid has '{}', only '#', '.' and '?' are allowed",
+                y + 1,
+                c
+            ));
+        }
+    }
+    Ok(Nonogram {
+        rows,
+        columns,
+        grid,
+    })
+}
+
 fn load_input(name: &str) -> Input {
     let file = File::open(name).unwrap_or_else(|_| panic!("No \"{}\" file found", name));
     let reader = BufReader::new(file);
@@ -150,6 +609,130 @@ fn load_input(name: &str) -> Input {
 mod tests {
     use super::*;
 
+    #[test]
+    fn arrangements() {
+        let row: Vec<char> = "?###????????".chars().collect();
+        let all: Vec<String> = Arrangements::new(&row, &[3, 2, 1]).collect();
+        assert_eq!(all.len(), 10);
+        assert_eq!(all[0], ".###.##.#...");
+        assert_eq!(all[9], ".###....##.#");
+        let mut sorted = all.clone();
+        sorted.sort();
+        assert_eq!(all, sorted);
+
+        let row: Vec<char> = "???.###".chars().collect();
+        let all: Vec<String> = Arrangements::new(&row, &[1, 1, 3]).collect();
+        assert_eq!(all, vec!["#.#.###"]);
+        assert_eq!(Arrangements::new(&row, &[4]).count(), 0);
+
+        // Every arrangement of "??????" with [1, 1] should show up about as often
+        let row: Vec<char> = "??????".chars().collect();
+        let all: Vec<String> = Arrangements::new(&row, &[1, 1]).collect();
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        let mut rng = Rng::new(2023);
+        for _ in 0..all.len() * 1000 {
+            let sample = sample_arrangement(&row, &[1, 1], &mut rng).unwrap();
+            *seen.entry(sample).or_default() += 1;
+        }
+        assert_eq!(seen.len(), all.len());
+        assert!(seen.values().all(|n| (800..1200).contains(n)));
+        assert_eq!(sample_arrangement(&row, &[7], &mut rng), None);
+    }
+
+    fn parse(text: &str) -> Result<Nonogram, String> {
+        let lines: Vec<String> = text.lines().map(|l| l.to_string()).collect();
+        parse_nonogram(&lines)
+    }
+
+    fn nonogram(text: &str) -> Nonogram {
+        parse(text).unwrap()
+    }
+
+    #[test]
+    fn nonogram_short_row() {
+        assert_eq!(
+            parse("1\n1\n\n1\n1\n\n#.\n.").err().unwrap(),
+            "Row 2 of the starting grid has 1 cells but there are 2 column clues"
+        );
+    }
+
+    #[test]
+    fn nonogram_unknown_cell() {
+        assert_eq!(
+            parse("1\n1\n\n1\n1\n\nx.\n.#").err().unwrap(),
+            "Row 1 of the starting grid has 'x', only '#', '.' and '?' are allowed"
+        );
+    }
+
+    #[test]
+    fn nonogram_missing_row() {
+        assert_eq!(
+            parse("1\n1\n\n1\n1\n\n#.").err().unwrap(),
+            "Starting grid has 1 rows but there are 2 row clues"
+        );
+    }
+
+    #[test]
+    fn nonogram_bad_clues() {
+        assert_eq!(
+            parse("1\n1,x\n\n1\n1").err().unwrap(),
+            "Clue \"x\" isn't a number"
+        );
+        assert_eq!(parse("1\n1").err().unwrap(), "Missing column clues");
+    }
+
+    #[test]
+    fn nonogram_solver() {
+        let puzzle = nonogram("1,1\n5\n5\n3\n1\n\n2\n4\n4\n4\n2");
+        let (solution, unique) = puzzle.solve();
+        let solution: Vec<String> = solution
+            .unwrap()
+            .iter()
+            .map(|r| r.iter().collect())
+            .collect();
+        assert_eq!(solution, vec![".#.#.", "#####", "#####", ".###.", "..#.."]);
+        assert!(unique);
+
+        // Both diagonals fit, the starting grid picks one
+        let (solution, unique) = nonogram("1\n1\n\n1\n1").solve();
+        assert!(solution.is_some());
+        assert!(!unique);
+        let (solution, unique) = nonogram("1\n1\n\n1\n1\n\n#?\n??").solve();
+        assert_eq!(solution, Some(vec![vec!['#', '.'], vec!['.', '#']]));
+        assert!(unique);
+
+        let (solution, _) = nonogram("2\n0\n\n1\n0").solve();
+        assert_eq!(solution, None);
+    }
+
+    #[test]
+    fn table_counter() {
+        let input: Vec<(Vec<char>, Vec<usize>)> = vec![
+            ("???.###".chars().collect(), vec![1, 1, 3]),
+            (".??..??...?##.".chars().collect(), vec![1, 1, 3]),
+            ("?###????????".chars().collect(), vec![3, 2, 1]),
+            ("#.#".chars().collect(), vec![2]),
+        ];
+        for (s, g) in &input {
+            assert_eq!(
+                count_arrangements(s, g),
+                crate::part_1(&vec![(s.clone(), g.clone())]) as u128
+            );
+        }
+        assert_eq!(count_unfolded(&input, 1), crate::part_1(&input) as u128);
+        assert_eq!(count_unfolded(&input, 5), crate::part_2(&input) as u128);
+
+        let (row, groups) = unfold(&['?'; 20], &[1], 5);
+        assert_eq!(row.len(), 104);
+        // Placing k groups of 1 in n cells is (n - k + 1) choose k
+        assert_eq!(count_arrangements(&row, &groups), 75_287_520);
+        // 124 cells with 25 groups is 100 choose 25 which doesn't fit in 64 bits
+        assert_eq!(
+            count_unfolded(&vec![("????".chars().collect(), vec![1])], 25),
+            242_519_269_720_337_121_015_504
+        );
+    }
+
     #[test]
     fn part_1() {
         let input = load_input("example");
@@ -1,137 +1,252 @@
+use std::fmt::Display;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
 
-type Map = Vec<Vec<Tile>>;
-
 fn main() {
     let input = load_input("input");
-    println!("Solution for part 1: {}", solve(&input, true));
-    println!("Solution for part 2: {}", solve(&input, false));
-}
-
-#[allow(dead_code)]
-fn print_map(map: &Map) {
-    for row in map {
-        for tile in row {
-            match tile {
-                Tile::Ash => print!("."),
-                Tile::Rock => print!("#"),
+    match std::env::args().nth(1).as_deref() {
+        None => {
+            for (part, smudges) in [(1, 0), (2, 1)] {
+                match solve(&input, smudges) {
+                    Ok(n) => println!("Solution for part {}: {}", part, n),
+                    Err(e) => println!("No solution for part {}: {}", part, e),
+                }
+            }
+        }
+        Some("mirrors") => {
+            let max_smudges = std::env::args()
+                .nth(2)
+                .map(|k| k.parse().expect("Smudge count should be a number"))
+                .unwrap_or(0);
+            for (i, pattern) in input.iter().enumerate() {
+                println!("Pattern {}:", i);
+                for reflection in reflections(pattern, max_smudges) {
+                    println!("  {}", reflection);
+                }
             }
         }
-        println!();
+        Some(other) => println!("Unknown command \"{}\", expected mirrors", other),
     }
 }
 
-fn solve(input: &[Map], strict: bool) -> usize {
+/// Sums the reflection lines that have exactly `smudges` differences, one per pattern
+fn solve(input: &[Pattern], smudges: usize) -> Result<usize, MirrorError> {
     let mut result = 0;
-    for map in input.iter() {
-        if let Some(n) = check_horizontal(&flip_map(map), strict) {
-            result += n;
-        } else if let Some(n) = check_horizontal(map, strict) {
-            result += n * 100
-        } else {
-            panic!("No symmetry found");
+    for (pattern, encoded) in input.iter().enumerate() {
+        let exact: Vec<Reflection> = reflections(encoded, smudges)
+            .into_iter()
+            .filter(|r| r.smudges() == smudges)
+            .collect();
+        match exact.as_slice() {
+            [reflection] => result += reflection.summary(),
+            [] => return Err(MirrorError::NotFound { pattern }),
+            _ => {
+                return Err(MirrorError::Ambiguous {
+                    pattern,
+                    lines: exact.len(),
+                })
+            }
         }
     }
-    result
+    Ok(result)
 }
 
-#[allow(clippy::needless_range_loop)]
-/// Creates a new Map that's flipped on it's side. Basically changing from map[row][column] to map[column][row].
-///
-/// This is done so that the same code can be used for the vertical and horizontal check.
-fn flip_map(map: &Map) -> Map {
-    let mut flipped_map: Map = vec![vec![Tile::Ash; map.len()]; map[0].len()];
-    for y in 0..map.len() {
-        for x in 0..map[0].len() {
-            flipped_map[x][y] = map[y][x];
-        }
-    }
-    flipped_map
-}
-
-fn check_horizontal(map: &Map, strict: bool) -> Option<usize> {
-    'map_loop: for i in 0..(map.len() - 1) {
-        // Flag used to determine if a difference was already found
-        // Can just be the strict flag since if there is a difference from the start
-        // a single difference will cause it to skip to the next Map
-        let mut found_difference = strict;
-        for (t_1, t_2) in map[i].iter().zip(map[i + 1].iter()) {
-            if t_1 != t_2 {
-                if found_difference {
-                    continue 'map_loop;
-                } else {
-                    found_difference = true;
-                }
+#[derive(Debug, PartialEq, Eq)]
+enum MirrorError {
+    NotFound { pattern: usize },
+    Ambiguous { pattern: usize, lines: usize },
+}
+
+impl Display for MirrorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MirrorError::NotFound { pattern } => {
+                write!(f, "pattern {} has no matching mirror line", pattern)
+            }
+            MirrorError::Ambiguous { pattern, lines } => {
+                write!(f, "pattern {} has {} matching mirror lines", pattern, lines)
             }
         }
-        // Found 2 rows/columns that are (almost) equal meaning there could be a mirror between them
-        if check_symmetry(map, i, strict) {
-            return Some(i + 1);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Orientation {
+    /// The line runs between two columns
+    Vertical,
+    /// The line runs between two rows
+    Horizontal,
+}
+
+/// (row, column) of a cell on one side of the mirror and of its reflection on the other side
+type Difference = ((usize, usize), (usize, usize));
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Reflection {
+    orientation: Orientation,
+    /// Number of columns left of the line or rows above it
+    position: usize,
+    differences: Vec<Difference>,
+}
+
+impl Reflection {
+    fn smudges(&self) -> usize {
+        self.differences.len()
+    }
+
+    /// The value the puzzle gives to this line
+    fn summary(&self) -> usize {
+        match self.orientation {
+            Orientation::Vertical => self.position,
+            Orientation::Horizontal => self.position * 100,
         }
     }
-    None
 }
 
-/// Check if 2 Maps are symmetrical if a mirror is between start_i and start_i + 1
-fn check_symmetry(map: &Map, start_i: usize, strict: bool) -> bool {
-    let mut found_smudge = false;
-    let mut x = start_i;
-    let mut y = start_i + 1;
-    loop {
-        if strict && map[x] != map[y] {
-            // If no differences allowed just check if the rows/columns are equal
-            return false;
-        } else {
-            for (t_1, t_2) in map[x].iter().zip(map[y].iter()) {
-                if t_1 != t_2 {
-                    if found_smudge {
-                        // Second difference found, mirror point is invalid
-                        return false;
-                    } else {
-                        found_smudge = true;
-                    }
-                }
-            }
+impl Display for Reflection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (kind, side) = match self.orientation {
+            Orientation::Vertical => ("vertical", "columns"),
+            Orientation::Horizontal => ("horizontal", "rows"),
+        };
+        write!(
+            f,
+            "{} line after {} {}, {} difference(s)",
+            kind,
+            self.position,
+            side,
+            self.smudges()
+        )?;
+        for ((y_1, x_1), (y_2, x_2)) in &self.differences {
+            write!(f, " ({},{})/({},{})", y_1, x_1, y_2, x_2)?;
         }
+        Ok(())
+    }
+}
+
+/// A pattern stored as one bitmask per row and one per column.
+///
+/// Bit `x` of a row is set if there's a rock in column `x`, and bit `y` of a column if there's one in row `y`,
+/// so comparing two rows or two columns is a single xor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Pattern {
+    rows: Vec<u64>,
+    columns: Vec<u64>,
+}
 
-        if x == 0 || y == map.len() - 1 {
-            break;
+impl Pattern {
+    /// Builds the column masks from the row masks of a pattern `width` tiles wide
+    fn from_rows(rows: Vec<u64>, width: usize) -> Pattern {
+        assert!(
+            rows.len() <= 64 && width <= 64,
+            "Patterns can be at most 64 tiles wide and tall"
+        );
+        let columns = (0..width)
+            .map(|x| {
+                rows.iter()
+                    .enumerate()
+                    .fold(0, |column, (y, row)| column | (row >> x & 1) << y)
+            })
+            .collect();
+        Pattern { rows, columns }
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for row in &self.rows {
+            for x in 0..self.columns.len() {
+                write!(f, "{}", if row >> x & 1 == 1 { '#' } else { '.' })?;
+            }
+            writeln!(f)?;
         }
-        x -= 1;
-        y += 1;
+        Ok(())
     }
-    if strict {
-        true
-    } else {
-        found_smudge
+}
+
+/// Finds every mirror line in the pattern that has at most `max_smudges` differing cell pairs, vertical lines first
+fn reflections(pattern: &Pattern, max_smudges: usize) -> Vec<Reflection> {
+    let mut found = Vec::new();
+    for (position, differences) in mirror_lines(&pattern.columns, max_smudges) {
+        found.push(Reflection {
+            orientation: Orientation::Vertical,
+            position,
+            // Differences between columns come back as (column, row)
+            differences: differences
+                .into_iter()
+                .map(|((x_1, y_1), (x_2, y_2))| ((y_1, x_1), (y_2, x_2)))
+                .collect(),
+        });
+    }
+    for (position, differences) in mirror_lines(&pattern.rows, max_smudges) {
+        found.push(Reflection {
+            orientation: Orientation::Horizontal,
+            position,
+            differences,
+        });
+    }
+    found
+}
+
+/// Checks every line between two rows (or columns) and returns the ones with at most `max_smudges` differences,
+/// together with the number of rows above the line and the differences as (row, bit) pairs
+fn mirror_lines(lines: &[u64], max_smudges: usize) -> Vec<(usize, Vec<Difference>)> {
+    let mut found = Vec::new();
+    'line_loop: for i in 0..(lines.len() - 1) {
+        // Walk outwards from the line until one side runs out of rows
+        let pairs = (0..=i).rev().zip((i + 1)..lines.len());
+        let mut smudges = 0;
+        for (above, below) in pairs.clone() {
+            smudges += (lines[above] ^ lines[below]).count_ones() as usize;
+            if smudges > max_smudges {
+                continue 'line_loop;
+            }
+        }
+        // Only lines that are kept need the positions of their differences
+        let mut differences = Vec::with_capacity(smudges);
+        for (above, below) in pairs {
+            let mut diff = lines[above] ^ lines[below];
+            while diff != 0 {
+                let bit = diff.trailing_zeros() as usize;
+                differences.push(((above, bit), (below, bit)));
+                diff &= diff - 1;
+            }
+        }
+        found.push((i + 1, differences));
     }
+    found
 }
 
-fn load_input(name: &str) -> Vec<Map> {
+/// Reads the patterns straight into bitmasks so they only get encoded once
+fn load_input(name: &str) -> Vec<Pattern> {
     let file = File::open(name).unwrap_or_else(|_| panic!("No \"{}\" file found", name));
     let reader = BufReader::new(file);
-    let mut input: Vec<Map> = Vec::new();
-    let mut rows: Map = Vec::new();
+    let mut input: Vec<Pattern> = Vec::new();
+    let mut rows: Vec<u64> = Vec::new();
+    let mut width = 0;
     for line in reader.lines() {
         let line = line.unwrap();
         if line.is_empty() {
-            input.push(rows);
+            input.push(Pattern::from_rows(rows, width));
             rows = Vec::new();
             continue;
         };
-        let mut row = Vec::new();
-        for c in line.chars() {
-            row.push(match c {
+        width = line.len();
+        let mut row = 0;
+        for (x, c) in line.chars().enumerate() {
+            let tile = match c {
                 '.' => Tile::Ash,
                 '#' => Tile::Rock,
                 _ => unreachable!(),
-            })
+            };
+            if tile == Tile::Rock {
+                row |= 1 << x;
+            }
         }
         rows.push(row);
     }
-    input.push(rows);
+    input.push(Pattern::from_rows(rows, width));
     input
 }
 
@@ -145,15 +260,187 @@ enum Tile {
 mod tests {
     use super::*;
 
+    #[test]
+    fn smudged_reflections() {
+        let input = load_input("example");
+        assert_eq!(input[0].rows[0], 0b011001101);
+        assert_eq!(input[0].columns[0], 0b1001101);
+        let first = reflections(&input[0], 1);
+        assert_eq!(
+            first,
+            vec![
+                Reflection {
+                    orientation: Orientation::Vertical,
+                    position: 5,
+                    differences: vec![],
+                },
+                Reflection {
+                    orientation: Orientation::Horizontal,
+                    position: 3,
+                    differences: vec![((0, 0), (5, 0))],
+                },
+            ]
+        );
+        assert_eq!(reflections(&input[0], 0), first[..1]);
+
+        // With enough smudges allowed every line is a mirror
+        let all = reflections(&input[1], 100);
+        assert_eq!(all.len(), (9 - 1) + (7 - 1));
+        for reflection in &all {
+            for ((y_1, x_1), (y_2, x_2)) in &reflection.differences {
+                let rock = |y: &usize, x: &usize| input[1].rows[*y] >> x & 1;
+                assert_ne!(rock(y_1, x_1), rock(y_2, x_2));
+            }
+        }
+    }
+
+    #[test]
+    fn mirror_errors() {
+        // .#
+        // ##
+        let plain = Pattern::from_rows(vec![0b10, 0b11], 2);
+        assert_eq!(plain.columns, vec![0b10, 0b11]);
+        assert_eq!(plain.to_string(), ".#\n##\n");
+        assert_eq!(
+            crate::solve(std::slice::from_ref(&plain), 0),
+            Err(MirrorError::NotFound { pattern: 0 })
+        );
+        assert_eq!(
+            crate::solve(&[plain], 1),
+            Err(MirrorError::Ambiguous {
+                pattern: 0,
+                lines: 2
+            })
+        );
+    }
+
+    /// Compares against the old search over `Vec<Vec<Tile>>` with a flipped copy of every pattern.
+    /// Run with `cargo test --release -- --ignored --nocapture bench_mirrors`
+    #[test]
+    #[ignore]
+    fn bench_mirrors() {
+        type Map = Vec<Vec<Tile>>;
+
+        fn flip_map(map: &Map) -> Map {
+            (0..map[0].len())
+                .map(|x| map.iter().map(|row| row[x]).collect())
+                .collect()
+        }
+
+        fn map_lines(map: &Map, max_smudges: usize) -> usize {
+            let mut total = 0;
+            'line_loop: for i in 0..(map.len() - 1) {
+                let mut smudges = 0;
+                for (above, below) in (0..=i).rev().zip((i + 1)..map.len()) {
+                    for (t_1, t_2) in map[above].iter().zip(map[below].iter()) {
+                        if t_1 != t_2 {
+                            smudges += 1;
+                            if smudges > max_smudges {
+                                continue 'line_loop;
+                            }
+                        }
+                    }
+                }
+                total += i + 1;
+            }
+            total
+        }
+
+        let path = std::env::temp_dir().join("day-13-bench");
+        let mut seed: u64 = 0x2023_0013;
+        let mut next = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+        let mut file = String::new();
+        for i in 0..2_000_000 {
+            if i > 0 {
+                file.push('\n');
+            }
+            let (width, height) = (3 + next() % 15, 3 + next() % 15);
+            let mut rows: Vec<String> = Vec::new();
+            for y in 0..height as usize {
+                // Copy earlier rows now and then so that some mirrors show up
+                if y > 0 && next() % 3 == 0 {
+                    let copied = rows[rows.len() - 1 - (next() as usize % y.min(3))].clone();
+                    rows.push(copied);
+                } else {
+                    rows.push(
+                        (0..width)
+                            .map(|_| if next() % 2 == 0 { '.' } else { '#' })
+                            .collect(),
+                    );
+                }
+            }
+            for row in rows {
+                file.push_str(&row);
+                file.push('\n');
+            }
+        }
+        let file = file.trim_end();
+        std::fs::write(&path, file).unwrap();
+
+        // Loading and both parts, the tile maps get flipped every time like the old solve did
+        let start = std::time::Instant::now();
+        let maps: Vec<Map> = std::fs::read_to_string(&path)
+            .unwrap()
+            .split("\n\n")
+            .map(|pattern| {
+                pattern
+                    .lines()
+                    .map(|line| {
+                        line.chars()
+                            .map(|c| if c == '#' { Tile::Rock } else { Tile::Ash })
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut map_total = 0;
+        for max_smudges in [0, 1] {
+            for map in &maps {
+                map_total +=
+                    map_lines(&flip_map(map), max_smudges) + map_lines(map, max_smudges) * 100;
+            }
+        }
+        let map_time = start.elapsed();
+
+        let start = std::time::Instant::now();
+        let patterns = load_input(path.to_str().unwrap());
+        let mut total = 0;
+        for max_smudges in [0, 1] {
+            for pattern in &patterns {
+                for (position, _) in mirror_lines(&pattern.columns, max_smudges) {
+                    total += position;
+                }
+                for (position, _) in mirror_lines(&pattern.rows, max_smudges) {
+                    total += position * 100;
+                }
+            }
+        }
+        let bit_time = start.elapsed();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(map_total, total);
+        println!(
+            "{} patterns, tile maps: {:?}, bitmasks: {:?}",
+            patterns.len(),
+            map_time,
+            bit_time
+        );
+    }
+
     #[test]
     fn part_1() {
         let input = load_input("example");
-        assert_eq!(crate::solve(&input, true), 405);
+        assert_eq!(crate::solve(&input, 0), Ok(405));
     }
 
     #[test]
     fn part_2() {
         let input = load_input("example");
-        assert_eq!(crate::solve(&input, false), 400);
+        assert_eq!(crate::solve(&input, 1), Ok(400));
     }
 }
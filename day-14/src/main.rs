@@ -1,12 +1,25 @@
-use std::collections::HashMap;
+use std::fmt::Display;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
 
 fn main() {
     let input = load_input("input");
+    if std::env::args().nth(1).as_deref() == Some("replay") {
+        let cycles = std::env::args()
+            .nth(2)
+            .map(|n| n.parse().expect("Cycle count should be a number"))
+            .unwrap_or(10);
+        let each_tilt = std::env::args().nth(3).as_deref() == Some("tilts");
+        print!("{}", replay(&input, cycles, each_tilt));
+        return;
+    }
+    let cycles = std::env::args()
+        .nth(1)
+        .map(|n| n.parse().expect("Cycle count should be a number"))
+        .unwrap_or(1_000_000_000);
     println!("Solution for part 1: {}", part_1(&input));
-    println!("Solution for part 2: {}", part_2(&input));
+    println!("Solution for part 2: {}", part_2(&input, cycles));
 }
 
 fn part_1(input: &[Vec<char>]) -> usize {
@@ -33,107 +46,316 @@ fn part_1(input: &[Vec<char>]) -> usize {
     load
 }
 
-fn measure_north_load(map: &[Vec<char>]) -> usize {
-    let mut load = 0;
-    let max_load = map.len();
-    for (y, row) in map.iter().enumerate() {
-        for c in row {
-            if *c == 'O' {
-                load += max_load - y;
+/// North load after the given number of spin cycles
+fn part_2(input: &[Vec<char>], cycles: usize) -> usize {
+    let history = History::record(Platform::new(input), Platform::spin);
+    history.state_after(cycles).north_load()
+}
+
+/// Shows the platform and its north load after every spin cycle, or after every tilt if `each_tilt` is set.
+///
+/// The cycle where the loop starts and the one where it gets back there are marked.
+fn replay(input: &[Vec<char>], cycles: usize, each_tilt: bool) -> String {
+    type Tilt = fn(&mut Platform);
+    let tilts: [(&str, Tilt); 4] = [
+        ("north", Platform::tilt_north),
+        ("west", Platform::tilt_west),
+        ("south", Platform::tilt_south),
+        ("east", Platform::tilt_east),
+    ];
+    let mut platform = Platform::new(input);
+    let (prefix, period) = brent(&platform, Platform::spin);
+    let marker = |cycle: usize| {
+        if cycle == prefix {
+            format!(" <- loop starts, {} cycles long", period)
+        } else if cycle == prefix + period {
+            format!(" <- back to cycle {}", prefix)
+        } else {
+            String::new()
+        }
+    };
+
+    let mut output = format!(
+        "Start, north load {}{}\n{}",
+        platform.north_load(),
+        marker(0),
+        platform
+    );
+    for cycle in 1..=cycles {
+        for (i, (direction, tilt)) in tilts.iter().enumerate() {
+            tilt(&mut platform);
+            if each_tilt && i < tilts.len() - 1 {
+                output += &format!(
+                    "\nCycle {} tilted {}, north load {}\n{}",
+                    cycle,
+                    direction,
+                    platform.north_load(),
+                    platform
+                );
             }
         }
+        let tilted = if each_tilt { " tilted east" } else { "" };
+        output += &format!(
+            "\nCycle {}{}, north load {}{}\n{}",
+            cycle,
+            tilted,
+            platform.north_load(),
+            marker(cycle),
+            platform
+        );
     }
-    load
+    output
 }
 
-fn part_2(input: &[Vec<char>]) -> usize {
-    let mut states: HashMap<Vec<Vec<char>>, usize> = HashMap::new();
-    let mut cur_map = input.to_vec();
-    states.insert(cur_map.clone(), 0);
-
-    // Do cycles until you find the loop, then only do before_loop + after_loop cycles
-    for x in 1..1000000000 {
-        cur_map = do_cycle(cur_map);
-        match states.get(&cur_map) {
-            Some(loop_index) => {
-                let loop_len = x - loop_index;
-                let mut cur_map = input.to_vec();
-                let needed_loops = ((1000000000 - loop_index) % loop_len) + loop_index;
-                for _ in 0..needed_loops {
-                    cur_map = do_cycle(cur_map);
+/// Brent's cycle detection, returns the number of steps before the loop and the length of the loop.
+///
+/// Only keeps two states around and steps them in place, so nothing gets allocated as long as `clone_from` doesn't.
+fn brent<T: Clone + PartialEq>(start: &T, mut step: impl FnMut(&mut T)) -> (usize, usize) {
+    // Find the period by moving the tortoise up to the hare at every power of two
+    let mut power = 1;
+    let mut period = 1;
+    let mut tortoise = start.clone();
+    let mut hare = start.clone();
+    step(&mut hare);
+    while tortoise != hare {
+        if power == period {
+            tortoise.clone_from(&hare);
+            power *= 2;
+            period = 0;
+        }
+        step(&mut hare);
+        period += 1;
+    }
+
+    // With the hare a period ahead, both meet where the loop starts
+    tortoise.clone_from(start);
+    hare.clone_from(start);
+    for _ in 0..period {
+        step(&mut hare);
+    }
+    let mut prefix = 0;
+    while tortoise != hare {
+        step(&mut tortoise);
+        step(&mut hare);
+        prefix += 1;
+    }
+    (prefix, period)
+}
+
+/// The platform as bitboards, bit `x` of a row is column `x` and bit `y` of a column is row `y`.
+///
+/// Tilting north or south rolls the column bitboards and tilting west or east the row bitboards,
+/// after which the other view of the round rocks is rebuilt in place.
+#[derive(Debug, PartialEq, Eq)]
+struct Platform {
+    width: usize,
+    height: usize,
+    round_rows: Vec<u128>,
+    round_columns: Vec<u128>,
+    cube_rows: Vec<u128>,
+    cube_columns: Vec<u128>,
+}
+
+impl Platform {
+    fn new(map: &[Vec<char>]) -> Platform {
+        let (width, height) = (map[0].len(), map.len());
+        assert!(
+            width <= 128 && height <= 128,
+            "Platforms can be at most 128 tiles wide and tall"
+        );
+        let mut platform = Platform {
+            width,
+            height,
+            round_rows: vec![0; height],
+            round_columns: vec![0; width],
+            cube_rows: vec![0; height],
+            cube_columns: vec![0; width],
+        };
+        for (y, row) in map.iter().enumerate() {
+            for (x, c) in row.iter().enumerate() {
+                match c {
+                    '.' => (),
+                    '#' => {
+                        platform.cube_rows[y] |= 1 << x;
+                        platform.cube_columns[x] |= 1 << y;
+                    }
+                    'O' => {
+                        platform.round_rows[y] |= 1 << x;
+                        platform.round_columns[x] |= 1 << y;
+                    }
+                    _ => unreachable!(),
                 }
-                return measure_north_load(&cur_map);
             }
-            None => states.insert(cur_map.clone(), x),
-        };
+        }
+        platform
+    }
+
+    fn spin(&mut self) {
+        self.tilt_north();
+        self.tilt_west();
+        self.tilt_south();
+        self.tilt_east();
+    }
+
+    fn tilt_north(&mut self) {
+        for (round, cube) in self.round_columns.iter_mut().zip(&self.cube_columns) {
+            *round = roll(*round, *cube, self.height, false);
+        }
+        transpose(&self.round_columns, &mut self.round_rows);
+    }
+
+    fn tilt_south(&mut self) {
+        for (round, cube) in self.round_columns.iter_mut().zip(&self.cube_columns) {
+            *round = roll(*round, *cube, self.height, true);
+        }
+        transpose(&self.round_columns, &mut self.round_rows);
+    }
+
+    fn tilt_west(&mut self) {
+        for (round, cube) in self.round_rows.iter_mut().zip(&self.cube_rows) {
+            *round = roll(*round, *cube, self.width, false);
+        }
+        transpose(&self.round_rows, &mut self.round_columns);
+    }
+
+    fn tilt_east(&mut self) {
+        for (round, cube) in self.round_rows.iter_mut().zip(&self.cube_rows) {
+            *round = roll(*round, *cube, self.width, true);
+        }
+        transpose(&self.round_rows, &mut self.round_columns);
+    }
+
+    fn north_load(&self) -> usize {
+        self.round_rows
+            .iter()
+            .enumerate()
+            .map(|(y, row)| row.count_ones() as usize * (self.height - y))
+            .sum()
     }
-    unreachable!()
 }
 
-fn do_cycle(mut map: Vec<Vec<char>>) -> Vec<Vec<char>> {
-    // As with day 13, flipping the map lets you reuse code so you only need to tilt the map horizontally
-    // The flipping is definitely not efficient but it's fast enough
+// Derived clone_from would allocate new vectors, this one reuses the existing ones
+impl Clone for Platform {
+    fn clone(&self) -> Self {
+        Platform {
+            width: self.width,
+            height: self.height,
+            round_rows: self.round_rows.clone(),
+            round_columns: self.round_columns.clone(),
+            cube_rows: self.cube_rows.clone(),
+            cube_columns: self.cube_columns.clone(),
+        }
+    }
 
-    // North
-    map = flip_map(&tilt_map(&flip_map(&map), false));
-    // West
-    map = tilt_map(&map, false);
-    // South
-    map = flip_map(&tilt_map(&flip_map(&map), true));
-    // East
-    map = tilt_map(&map, true);
-    map
+    fn clone_from(&mut self, source: &Self) {
+        self.width = source.width;
+        self.height = source.height;
+        self.round_rows.clone_from(&source.round_rows);
+        self.round_columns.clone_from(&source.round_columns);
+        self.cube_rows.clone_from(&source.cube_rows);
+        self.cube_columns.clone_from(&source.cube_columns);
+    }
 }
 
-// Go from the edge you tilt towards and keep track of the last spot that rocks can roll to in a row
-fn tilt_map(map: &[Vec<char>], reverse: bool) -> Vec<Vec<char>> {
-    let mut new_map = vec![vec!['.'; map[0].len()]; map.len()];
-    let mut x_iter: Vec<usize> = (0..map[0].len()).collect();
-    if reverse {
-        x_iter.reverse();
+impl Display for Platform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (round, cube) in self.round_rows.iter().zip(&self.cube_rows) {
+            for x in 0..self.width {
+                let tile = if round >> x & 1 == 1 {
+                    'O'
+                } else if cube >> x & 1 == 1 {
+                    '#'
+                } else {
+                    '.'
+                };
+                write!(f, "{}", tile)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
     }
-    for y in 0..map.len() {
-        let mut last_free = 0;
-        if reverse {
-            last_free = map[0].len() - 1;
+}
+
+/// Bits `start..end` set
+fn bit_range(start: usize, end: usize) -> u128 {
+    let below = |n: usize| if n >= 128 { u128::MAX } else { (1 << n) - 1 };
+    below(end) & !below(start)
+}
+
+/// Rolls the round rocks of a line `len` long towards bit 0, or towards the last bit if `reverse` is set.
+///
+/// The cube rocks split the line into stretches and every stretch just keeps its number of round rocks.
+fn roll(round: u128, cube: u128, len: usize, reverse: bool) -> u128 {
+    let mut rolled = 0;
+    let mut start = 0;
+    let mut cubes_left = cube;
+    loop {
+        let end = if cubes_left == 0 {
+            len
+        } else {
+            cubes_left.trailing_zeros() as usize
+        };
+        let count = (round & bit_range(start, end)).count_ones() as usize;
+        rolled |= if reverse {
+            bit_range(end - count, end)
+        } else {
+            bit_range(start, start + count)
+        };
+        if cubes_left == 0 {
+            return rolled;
         }
-        for x in &x_iter {
-            match map[y][*x] {
-                '.' => (),
-                '#' => {
-                    // 0 check because of usize subtraction
-                    if reverse && x > &0 {
-                        last_free = x - 1;
-                    } else {
-                        last_free = x + 1;
-                    }
-                    new_map[y][*x] = '#';
-                }
-                'O' => {
-                    new_map[y][last_free] = 'O';
-                    // 0 check because of usize subtraction
-                    if reverse && last_free > 0 {
-                        last_free -= 1;
-                    } else {
-                        last_free += 1;
-                    }
-                }
-                _ => unreachable!(),
-            }
+        cubes_left &= cubes_left - 1;
+        start = end + 1;
+    }
+}
+
+/// Writes the bits of `lines` into `transposed` so that bit `j` of line `i` becomes bit `i` of line `j`
+fn transpose(lines: &[u128], transposed: &mut [u128]) {
+    transposed.fill(0);
+    for (i, line) in lines.iter().enumerate() {
+        let mut bits = *line;
+        while bits != 0 {
+            transposed[bits.trailing_zeros() as usize] |= 1 << i;
+            bits &= bits - 1;
         }
     }
-    new_map
 }
 
-#[allow(clippy::needless_range_loop)]
-fn flip_map(map: &[Vec<char>]) -> Vec<Vec<char>> {
-    let mut flipped_map: Vec<Vec<char>> = vec![vec!['.'; map.len()]; map[0].len()];
-    for y in 0..map.len() {
-        for x in 0..map[0].len() {
-            flipped_map[x][y] = map[y][x];
+/// Every state an iterated state machine goes through until it gets back to one it was already in
+struct History<T> {
+    states: Vec<T>,
+    /// Number of steps before the loop is entered
+    prefix: usize,
+    /// Number of steps it takes to go around the loop once
+    period: usize,
+}
+
+impl<T: Clone + PartialEq> History<T> {
+    /// Finds the loop with `brent` first, so the states only get stored once the loop is known
+    /// and the search itself doesn't allocate.
+    fn record(start: T, mut step: impl FnMut(&mut T)) -> History<T> {
+        let (prefix, period) = brent(&start, &mut step);
+        let mut states = Vec::with_capacity(prefix + period);
+        let mut state = start;
+        for _ in 0..prefix + period {
+            states.push(state.clone());
+            step(&mut state);
+        }
+        History {
+            states,
+            prefix,
+            period,
+        }
+    }
+
+    /// The state after `n` steps, without stepping again
+    fn state_after(&self, n: usize) -> &T {
+        if n < self.prefix {
+            &self.states[n]
+        } else {
+            &self.states[self.prefix + (n - self.prefix) % self.period]
         }
     }
-    flipped_map
 }
 
 fn load_input(name: &str) -> Vec<Vec<char>> {
@@ -146,19 +368,233 @@ fn load_input(name: &str) -> Vec<Vec<char>> {
     input
 }
 
-#[allow(dead_code)]
-fn print_map(map: &[Vec<char>]) {
-    for row in map {
-        for tile in row {
-            print!("{}", tile)
-        }
-        println!();
-    }
-}
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// Same platform as the example file, which isn't checked in
+    fn example() -> Vec<Vec<char>> {
+        [
+            "O....#....",
+            "O.OO#....#",
+            ".....##...",
+            "OO.#O....O",
+            ".O.....O#.",
+            "O.#..O.#.#",
+            "..O..#O..O",
+            ".......O..",
+            "#....###..",
+            "#OO..#....",
+        ]
+        .iter()
+        .map(|row| row.chars().collect())
+        .collect()
+    }
+
+    // The original spin cycle on the char map, to check the bitboards against
+
+    fn measure_north_load(map: &[Vec<char>]) -> usize {
+        let mut load = 0;
+        let max_load = map.len();
+        for (y, row) in map.iter().enumerate() {
+            for c in row {
+                if *c == 'O' {
+                    load += max_load - y;
+                }
+            }
+        }
+        load
+    }
+
+    fn do_cycle(mut map: Vec<Vec<char>>) -> Vec<Vec<char>> {
+        // As with day 13, flipping the map lets you reuse code so you only need to tilt the map horizontally
+        // The flipping is definitely not efficient but it's fast enough
+
+        // North
+        map = flip_map(&tilt_map(&flip_map(&map), false));
+        // West
+        map = tilt_map(&map, false);
+        // South
+        map = flip_map(&tilt_map(&flip_map(&map), true));
+        // East
+        map = tilt_map(&map, true);
+        map
+    }
+
+    // Go from the edge you tilt towards and keep track of the last spot that rocks can roll to in a row
+    fn tilt_map(map: &[Vec<char>], reverse: bool) -> Vec<Vec<char>> {
+        let mut new_map = vec![vec!['.'; map[0].len()]; map.len()];
+        let mut x_iter: Vec<usize> = (0..map[0].len()).collect();
+        if reverse {
+            x_iter.reverse();
+        }
+        for y in 0..map.len() {
+            let mut last_free = 0;
+            if reverse {
+                last_free = map[0].len() - 1;
+            }
+            for x in &x_iter {
+                match map[y][*x] {
+                    '.' => (),
+                    '#' => {
+                        // 0 check because of usize subtraction
+                        if reverse && x > &0 {
+                            last_free = x - 1;
+                        } else {
+                            last_free = x + 1;
+                        }
+                        new_map[y][*x] = '#';
+                    }
+                    'O' => {
+                        new_map[y][last_free] = 'O';
+                        // 0 check because of usize subtraction
+                        if reverse && last_free > 0 {
+                            last_free -= 1;
+                        } else {
+                            last_free += 1;
+                        }
+                    }
+                    _ => unreachable!(),
+                }
+            }
+        }
+        new_map
+    }
+
+    #[allow(clippy::needless_range_loop)]
+    fn flip_map(map: &[Vec<char>]) -> Vec<Vec<char>> {
+        let mut flipped_map: Vec<Vec<char>> = vec![vec!['.'; map.len()]; map[0].len()];
+        for y in 0..map.len() {
+            for x in 0..map[0].len() {
+                flipped_map[x][y] = map[y][x];
+            }
+        }
+        flipped_map
+    }
+
+    #[test]
+    fn history() {
+        // Squaring modulo a number walks into a loop after a few steps
+        let step = |x: &mut u64| *x = (*x * *x + 1) % 1009;
+        let history = History::record(3, step);
+        assert_eq!(history.states.len(), history.prefix + history.period);
+        assert!(history.prefix > 0);
+        let mut x = 3;
+        for n in 0..5000 {
+            assert_eq!(*history.state_after(n), x);
+            step(&mut x);
+        }
+
+        // A loop right from the start
+        let history = History::record(0, |x: &mut u8| *x = (*x + 1) % 7);
+        assert_eq!((history.prefix, history.period), (0, 7));
+        assert_eq!(
+            *history.state_after(1_000_000_000),
+            (1_000_000_000_usize % 7) as u8
+        );
+
+        let input = example();
+        assert_eq!(brent(&Platform::new(&input), Platform::spin), (3, 7));
+        let mut map = input.to_vec();
+        for cycles in 0..40 {
+            assert_eq!(
+                crate::part_2(&input, cycles),
+                measure_north_load(&map),
+                "after {} cycles",
+                cycles
+            );
+            map = do_cycle(map);
+        }
+    }
+
+    #[test]
+    fn bitboard() {
+        let rendered = |map: &[Vec<char>]| {
+            map.iter()
+                .map(|row| row.iter().collect::<String>() + "\n")
+                .collect::<String>()
+        };
+
+        let small = example();
+        // A 100 by 120 platform to get past 64 bits in both directions
+        let mut seed: u64 = 0x2023_0014;
+        let big: Vec<Vec<char>> = (0..100)
+            .map(|_| {
+                (0..120)
+                    .map(|_| {
+                        seed ^= seed << 13;
+                        seed ^= seed >> 7;
+                        seed ^= seed << 17;
+                        ['.', '.', '.', 'O', 'O', '#'][(seed % 6) as usize]
+                    })
+                    .collect()
+            })
+            .collect();
+
+        for input in [small, big] {
+            let mut platform = Platform::new(&input);
+            assert_eq!(platform.to_string(), rendered(&input));
+            platform.tilt_north();
+            let north = flip_map(&tilt_map(&flip_map(&input), false));
+            assert_eq!(platform.to_string(), rendered(&north));
+            assert_eq!(platform.north_load(), crate::part_1(&input));
+            platform.tilt_east();
+            assert_eq!(platform.to_string(), rendered(&tilt_map(&north, true)));
+
+            let mut platform = Platform::new(&input);
+            let mut map = input.clone();
+            for _ in 0..10 {
+                platform.spin();
+                map = do_cycle(map);
+                assert_eq!(platform.to_string(), rendered(&map));
+                assert_eq!(platform.north_load(), measure_north_load(&map));
+            }
+        }
+    }
+
+    #[test]
+    fn replay() {
+        let input = example();
+        let cycles = crate::replay(&input, 10, false);
+        let headers: Vec<&str> = cycles.lines().filter(|l| l.contains("load")).collect();
+        assert_eq!(headers.len(), 11);
+        assert_eq!(headers[0], "Start, north load 104");
+        assert_eq!(
+            headers[3],
+            "Cycle 3, north load 69 <- loop starts, 7 cycles long"
+        );
+        assert_eq!(headers[10], "Cycle 10, north load 69 <- back to cycle 3");
+        let after_one = [
+            ".....#....",
+            "....#...O#",
+            "...OO##...",
+            ".OO#......",
+            ".....OOO#.",
+            ".O#...O#.#",
+            "....O#....",
+            "......OOOO",
+            "#...O###..",
+            "#..OO#....",
+        ];
+        let first: Vec<&str> = cycles.lines().skip(13).take(10).collect();
+        assert_eq!(first, after_one);
+
+        let tilts = crate::replay(&input, 2, true);
+        let headers: Vec<&str> = tilts.lines().filter(|l| l.contains("load")).collect();
+        assert_eq!(headers.len(), 9);
+        assert_eq!(headers[1], "Cycle 1 tilted north, north load 136");
+        assert_eq!(headers[4], "Cycle 1 tilted east, north load 87");
+        // The platform after the east tilt is the one after the whole cycle
+        let platforms = |output: &str| -> Vec<String> {
+            output
+                .split("\n\n")
+                .map(|block| block.split_once('\n').unwrap().1.trim_end().to_string())
+                .collect()
+        };
+        assert_eq!(platforms(&tilts)[4], platforms(&cycles)[1]);
+        assert_eq!(platforms(&tilts)[8], platforms(&cycles)[2]);
+    }
+
     #[test]
     fn part_1() {
         let input = load_input("example");
@@ -168,6 +604,6 @@ mod tests {
     #[test]
     fn part_2() {
         let input = load_input("example");
-        assert_eq!(crate::part_2(&input), 64);
+        assert_eq!(crate::part_2(&input, 1_000_000_000), 64);
     }
 }